@@ -0,0 +1,156 @@
+//! The rsp10 command line tool, scaffolding the pages
+//!
+//! ```text
+//! rsp10 new-page orders txtCustomer ddStatus dtDue:Option<chrono::NaiveDate> --dir src/pages
+//! rsp10 template src/pages/orders.rs
+//! ```
+//!
+//! `new-page` writes the page module and its template, laid out from the fields, and
+//! adds the page to the `rsp_routes!` of the `mod.rs` in the directory, if there is one.
+//! `template` lays out the fields of an existing state again, after they changed -
+//! only the part of the template between the `rsp10 fields` markers is replaced.
+
+mod page;
+mod template;
+
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+Usage:
+  rsp10 new-page <name> [<field>[:<type>]...] [--dir <dir>] [--templates <dir>] [--route <path>] [--auth <type>]
+      Create the page module <dir>/<name>.rs and the template <name>.mustache,
+      and add the page to the rsp_routes! of <dir>/mod.rs, if there is one.
+      The element of each field comes from the prefix of its name: txtName, ddStatus, ...
+
+  rsp10 template <page.rs> [--struct <name>] [--templates <dir>] [--print]
+      Lay out the fields of the state in the template of the page again,
+      between its rsp10 fields markers - or create it, if it is not there.
+      --print only prints them.
+
+The templates are in --templates, else $RSP10_TEMPLATE_ROOT, else ./templates";
+
+/// The positional arguments and the `--name value` options of the command
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>, flags: &[&str]) -> Args {
+        let mut positional = vec![];
+        let mut options = vec![];
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if flags.contains(&name) => options.push((name.to_string(), None)),
+                Some(name) => options.push((name.to_string(), args.next())),
+                None => positional.push(arg),
+            }
+        }
+        Args { positional, options }
+    }
+
+    fn option(&self, name: &str) -> Result<Option<String>, String> {
+        match self.options.iter().find(|(n, _)| n == name) {
+            Some((_, Some(value))) => Ok(Some(value.clone())),
+            Some((_, None)) => Err(format!("--{} needs a value", name)),
+            None => Ok(None),
+        }
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(n, _)| n == name)
+    }
+
+    fn check_options(&self, known: &[&str]) -> Result<(), String> {
+        match self.options.iter().find(|(n, _)| !known.contains(&n.as_str())) {
+            Some((name, _)) => Err(format!("unknown option --{}", name)),
+            None => Ok(()),
+        }
+    }
+
+    fn template_root(&self) -> Result<PathBuf, String> {
+        let root = match self.option("templates")? {
+            Some(root) => root,
+            None => std::env::var("RSP10_TEMPLATE_ROOT").unwrap_or_else(|_| "./templates".to_string()),
+        };
+        Ok(PathBuf::from(root))
+    }
+}
+
+fn new_page(args: Args) -> Result<(), String> {
+    args.check_options(&["dir", "templates", "route", "auth"])?;
+    let mut positional = args.positional.iter();
+    let name = positional.next().ok_or("the name of the page is missing")?.clone();
+    let fields = positional
+        .map(|field| match field.split_once(':') {
+            Some((name, ty)) => (name.to_string(), Some(ty.to_string())),
+            None => (field.to_string(), None),
+        })
+        .collect();
+    let page = page::RspNewPage {
+        route: args.option("route")?.unwrap_or_else(|| format!("/{}", name)),
+        auth: args.option("auth")?.unwrap_or_else(|| "CookiePageAuth".to_string()),
+        name,
+        fields,
+    };
+    let dir = PathBuf::from(args.option("dir")?.unwrap_or_else(|| ".".to_string()));
+
+    let written = page::new_page(&page, &dir, &args.template_root()?)?;
+    for path in &written {
+        println!("Wrote {}", path.display());
+    }
+    if !written.iter().any(|path| path.ends_with("mod.rs")) {
+        println!("Add the page to the routes: \"{}\" => {},", page.route, page.name);
+    }
+    Ok(())
+}
+
+fn regenerate_template(args: Args) -> Result<(), String> {
+    args.check_options(&["struct", "templates", "print"])?;
+    let source_path = args.positional.first().ok_or("the source of the page is missing")?;
+    let source = std::fs::read_to_string(source_path).map_err(|e| format!("can not read {}: {}", source_path, e))?;
+    let page = template::RspPageSource::parse(&source, args.option("struct")?.as_deref())?;
+
+    if args.flag("print") {
+        print!("{}", page.fields_section()?);
+        return Ok(());
+    }
+    let stem = Path::new(source_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = page.template_name().unwrap_or(stem);
+    let path = args.template_root()?.join(format!("{}.mustache", name));
+
+    let template = match std::fs::read_to_string(&path) {
+        Ok(existing) => template::replace_fields_section(&existing, &page.fields_section()?)
+            .map_err(|e| format!("{}: {} - see --print", path.display(), e))?,
+        Err(_) => page.template(&page::title(&name))?,
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("can not create {}: {}", dir.display(), e))?;
+    }
+    std::fs::write(&path, template).map_err(|e| format!("can not write {}: {}", path.display(), e))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let result = match args.next().as_deref() {
+        Some("new-page") => new_page(Args::parse(args, &[])),
+        Some("template") => regenerate_template(Args::parse(args, &["print"])),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            return;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,299 @@
+//! New page modules
+//!
+//! The module has the key and the state structs, the functions returning the items
+//! of the dropdowns, radio buttons and multi-selects, and an `RspState` impl to fill in.
+//! The fields are given as `name` or `name:Type`; the prefix of the name decides the
+//! element, as with the derive, and the type defaults to the usual one of the element.
+
+use std::path::{Path, PathBuf};
+
+use rsp10_field_kind::{field_kind, Kind};
+
+pub struct RspNewPage {
+    pub name: String,
+    pub fields: Vec<(String, Option<String>)>,
+    pub auth: String,
+    pub route: String,
+}
+
+/// `order_lines` -> `OrderLines`
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// `order_lines` -> `Order lines`
+pub fn title(name: &str) -> String {
+    let words = name.replace('_', " ");
+    let mut chars = words.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// The kind of the field, as the derive will see it
+fn kind_of(name: &str) -> Result<Kind, String> {
+    let field: syn::FieldsNamed = syn::parse_str(&format!("{{ {}: () }}", name))
+        .map_err(|_| format!("'{}' is not a valid field name", name))?;
+    let field = field.named.first().unwrap();
+    field_kind(field).map(|fk| fk.kind).map_err(|e| e.to_string())
+}
+
+fn default_type(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::Number | Kind::Select(_) | Kind::Radio(_) => "i32",
+        Kind::Date => "Option<chrono::NaiveDate>",
+        Kind::Check => "bool",
+        Kind::MultiSelect(_) => "Vec<i32>",
+        Kind::Button => "()",
+        _ => "String",
+    }
+}
+
+/// The function returning the items, named by the convention of the derive
+fn items_source(name: &str, ty: &str, kind: &Kind) -> Option<String> {
+    let (param, items, item) = match kind {
+        Kind::Select(_) => ("value", "HtmlSelect", ty),
+        Kind::Radio(_) => ("value", "HtmlRadio", ty),
+        Kind::MultiSelect(_) => {
+            let item = ty
+                .trim()
+                .strip_prefix("Vec<")
+                .and_then(|t| t.strip_suffix('>'))
+                .unwrap_or(ty);
+            ("values", "HtmlMultiSelect", item.trim())
+        }
+        _ => return None,
+    };
+    Some(format!(
+        "pub fn get_{name}(_{param}: {ty}) -> {items}<{item}> {{\n    \
+         // The items, with items.item(\"label\", value)\n    \
+         {items}::default()\n}}\n"
+    ))
+}
+
+impl RspNewPage {
+    /// The source of the module, `imports` being whether it can use `super::imports::*`
+    /// like the example pages do
+    pub fn module(&self, imports: bool) -> Result<String, String> {
+        let camel = camel_case(&self.name);
+        let key = format!("{}Key", camel);
+        let mut fields = String::from("    message: String,\n");
+        let mut sources = String::new();
+
+        for (name, ty) in &self.fields {
+            let kind = kind_of(name)?;
+            if let Kind::Grid = kind {
+                return Err(format!(
+                    "{}: a grid needs a row struct - add it to the state afterwards, and regenerate the template with 'rsp10 template'",
+                    name
+                ));
+            }
+            let ty = ty.clone().unwrap_or_else(|| default_type(&kind).to_string());
+            syn::parse_str::<syn::Type>(&ty).map_err(|_| format!("{}: '{}' is not a valid type", name, ty))?;
+            if let Kind::Button = kind {
+                fields.push_str("    #[serde(skip)]\n");
+            }
+            fields.push_str(&format!("    {}: {},\n", name, ty));
+            if let Some(source) = items_source(name, &ty, &kind) {
+                sources.push('\n');
+                sources.push_str(&source);
+            }
+        }
+
+        let uses = if imports {
+            "use super::imports::*;\n".to_string()
+        } else {
+            "use rsp10::*;\nuse rsp10_derive::RspState as RspStateDerive;\nuse serde_derive::{Deserialize, Serialize};\n"
+                .to_string()
+        };
+        let auth = &self.auth;
+
+        Ok(format!(
+            r#"#![allow(non_snake_case)]
+
+{uses}
+#[derive(Debug, Clone, Serialize, Deserialize, Default, rsp10_derive::RspKey)]
+pub struct {key} {{
+    pub id: Option<i32>,
+}}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, RspStateDerive)]
+#[rsp_key({key})]
+#[rsp_auth({auth})]
+#[rsp_template("{name}")]
+pub struct PageState {{
+{fields}}}
+{sources}
+impl RspState<{key}, {auth}> for PageState {{
+    fn get_state(_auth: &{auth}, _key: {key}) -> PageState {{
+        PageState {{
+            ..Default::default()
+        }}
+    }}
+
+    fn fill_data<'a>(ri: RspInfo<'a, Self, {key}, {auth}>) -> RspFillDataResult<Self> {{
+        Self::derive_auto_fill_data_impl(ri)
+    }}
+
+    fn event_handler<'a>(ri: RspInfo<'a, Self, {key}, {auth}>) -> RspEventHandlerResult<Self, {key}> {{
+        let action = rsp10::RspAction::Render;
+        let initial_state = ri.initial_state;
+        let state = ri.state;
+
+        if ri.event.event == "submit" && !ri.state_none {{
+            // Save the state here
+        }}
+        RspEventHandlerResult {{
+            initial_state,
+            state,
+            action,
+            new_auth: None,
+        }}
+    }}
+}}
+"#,
+            name = self.name,
+        ))
+    }
+
+    /// Add the module and its route to the `mod.rs` of the pages, if it has the `rsp_routes!`
+    pub fn register(&self, mod_rs: &str) -> Result<String, String> {
+        let routes = mod_rs
+            .find("rsp_routes!")
+            .ok_or_else(|| "there is no rsp_routes! to add the route to".to_string())?;
+        let routes_end = mod_rs[routes..]
+            .find("\n}")
+            .map(|end| routes + end + 1)
+            .ok_or_else(|| "the rsp_routes! is not closed".to_string())?;
+        let is_mod_line = |line: &str| line.starts_with("mod ") && line.ends_with(';');
+        if mod_rs.lines().any(|line| line == format!("mod {};", self.name)) {
+            return Err(format!("the module {} is there already", self.name));
+        }
+
+        let declaration = if mod_rs.contains("#[path = ") {
+            format!("#[path = \"{0}.rs\"]\nmod {0};\n", self.name)
+        } else {
+            format!("mod {};\n", self.name)
+        };
+        // After the last module, or at the top
+        let mut offset = 0;
+        let mut declaration_at = 0;
+        for line in mod_rs[..routes].split_inclusive('\n') {
+            offset += line.len();
+            if is_mod_line(line.trim_end()) {
+                declaration_at = offset;
+            }
+        }
+
+        // The last route may be written without the trailing comma
+        let last_route_end = routes + mod_rs[routes..routes_end].trim_end().len();
+        let comma = match mod_rs[..last_route_end].chars().last() {
+            Some(',') | Some('{') => "",
+            _ => ",",
+        };
+
+        Ok(format!(
+            "{}{}{}{}{}    \"{}\" => {},\n{}",
+            &mod_rs[..declaration_at],
+            declaration,
+            &mod_rs[declaration_at..last_route_end],
+            comma,
+            &mod_rs[last_route_end..routes_end],
+            self.route,
+            self.name,
+            &mod_rs[routes_end..]
+        ))
+    }
+}
+
+/// Create the module and the template of the page, and add it to the routes
+pub fn new_page(page: &RspNewPage, dir: &Path, template_root: &Path) -> Result<Vec<PathBuf>, String> {
+    syn::parse_str::<syn::Ident>(&page.name).map_err(|_| format!("'{}' is not a valid module name", page.name))?;
+    let module_path = dir.join(format!("{}.rs", page.name));
+    let template_path = template_root.join(format!("{}.mustache", page.name));
+    for path in [&module_path, &template_path] {
+        if path.exists() {
+            return Err(format!("{} exists already", path.display()));
+        }
+    }
+
+    let module = page.module(dir.join("imports.rs").exists())?;
+    let template = crate::template::RspPageSource::parse(&module, None)?.template(&title(&page.name))?;
+
+    let mod_rs_path = dir.join("mod.rs");
+    let mod_rs = match std::fs::read_to_string(&mod_rs_path) {
+        Ok(mod_rs) if mod_rs.contains("rsp_routes!") => Some(page.register(&mod_rs)?),
+        _ => None,
+    };
+
+    let write = |path: &Path, contents: &str| {
+        std::fs::write(path, contents).map_err(|e| format!("can not write {}: {}", path.display(), e))
+    };
+    std::fs::create_dir_all(dir).map_err(|e| format!("can not create {}: {}", dir.display(), e))?;
+    std::fs::create_dir_all(template_root).map_err(|e| format!("can not create {}: {}", template_root.display(), e))?;
+    write(&module_path, &module)?;
+    write(&template_path, &template)?;
+    let mut written = vec![module_path, template_path];
+    if let Some(mod_rs) = mod_rs {
+        write(&mod_rs_path, &mod_rs)?;
+        written.push(mod_rs_path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page() -> RspNewPage {
+        RspNewPage {
+            name: "orders".to_string(),
+            fields: vec![],
+            auth: "NoPageAuth".to_string(),
+            route: "/orders".to_string(),
+        }
+    }
+
+    #[test]
+    fn register_adds_the_module_and_the_route() {
+        let mod_rs = "mod imports;\nmod login;\n\nrsp10::rsp_routes! {\n    \"/login\" => login,\n}\n";
+        assert_eq!(
+            page().register(mod_rs).unwrap(),
+            "mod imports;\nmod login;\nmod orders;\n\nrsp10::rsp_routes! {\n    \"/login\" => login,\n    \"/orders\" => orders,\n}\n"
+        );
+    }
+
+    #[test]
+    fn register_adds_the_missing_comma() {
+        let mod_rs = "#[path = \"login.rs\"]\nmod login;\n\nrsp10::rsp_routes! {\n    \"/login\" => login\n}\n";
+        assert_eq!(
+            page().register(mod_rs).unwrap(),
+            "#[path = \"login.rs\"]\nmod login;\n#[path = \"orders.rs\"]\nmod orders;\n\n\
+             rsp10::rsp_routes! {\n    \"/login\" => login,\n    \"/orders\" => orders,\n}\n"
+        );
+    }
+
+    #[test]
+    fn register_into_empty_routes() {
+        assert_eq!(
+            page().register("rsp10::rsp_routes! {\n}\n").unwrap(),
+            "mod orders;\nrsp10::rsp_routes! {\n    \"/orders\" => orders,\n}\n"
+        );
+    }
+
+    #[test]
+    fn register_refuses_what_it_can_not_do() {
+        assert!(page().register("mod login;\n").is_err());
+        assert!(page().register("rsp10::rsp_routes! {\n    \"/login\" => login,").is_err());
+        assert!(page().register("mod orders;\nrsp10::rsp_routes! {\n}\n").is_err());
+    }
+}
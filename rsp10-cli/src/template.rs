@@ -0,0 +1,279 @@
+//! The templates laid out from the fields of the state
+//!
+//! Each field gets the partial of its element, the same way the derive picks
+//! the element - from the `#[rsp(...)]` attribute, else the name prefix:
+//!
+//! ```text
+//! {{#txtName}} {{> html/text }} {{/txtName}}<br/>
+//! ```
+//!
+//! The elements go between the `{{! rsp10 fields begin }}` and `{{! rsp10 fields end }}`
+//! comments, so that the section can be regenerated after the state changes,
+//! leaving the rest of the template as it was edited.
+
+use syn::{Field, Fields, Item, ItemStruct};
+
+use rsp10_field_kind::{field_kind, Kind};
+
+pub const FIELDS_BEGIN: &str = "{{! rsp10 fields begin }}";
+pub const FIELDS_END: &str = "{{! rsp10 fields end }}";
+
+/// The state struct of a page, with the structs of the rows of its grids
+pub struct RspPageSource {
+    pub state: ItemStruct,
+    pub structs: Vec<ItemStruct>,
+}
+
+impl RspPageSource {
+    /// Find the state struct in the source - the one deriving `RspState`,
+    /// or the one named, if there are several
+    pub fn parse(source: &str, name: Option<&str>) -> Result<Self, String> {
+        let file = syn::parse_file(source).map_err(|e| format!("can not parse the source: {}", e))?;
+        let mut structs = vec![];
+        collect_structs(&file.items, &mut structs);
+
+        let mut states: Vec<&ItemStruct> = match name {
+            Some(name) => structs.iter().filter(|s| s.ident == name).collect(),
+            None => structs.iter().filter(|s| derives_state(s)).collect(),
+        };
+        let state = match (states.pop(), states.is_empty()) {
+            (Some(state), true) => state.clone(),
+            (Some(_), false) => return Err("several state structs, choose one with --struct".to_string()),
+            (None, _) => match name {
+                Some(name) => return Err(format!("no struct {}", name)),
+                None => return Err("no struct deriving RspState".to_string()),
+            },
+        };
+        Ok(RspPageSource { state, structs })
+    }
+
+    /// The template set with `#[rsp_template("...")]`
+    pub fn template_name(&self) -> Option<String> {
+        self.state
+            .attrs
+            .iter()
+            .find(|a| a.path().is_ident("rsp_template"))
+            .and_then(|a| a.parse_args::<syn::LitStr>().ok())
+            .map(|name| name.value())
+    }
+
+    fn row_struct(&self, field: &Field) -> Option<&ItemStruct> {
+        let row = vec_item_type(&field.ty)?;
+        self.structs.iter().find(|s| s.ident == row)
+    }
+
+    /// The elements of the fields, as they go between the markers
+    pub fn fields_section(&self) -> Result<String, String> {
+        let mut out = String::new();
+        for field in named_fields(&self.state) {
+            let name = field.ident.as_ref().unwrap().to_string();
+            let fk = field_kind(field).map_err(|e| format!("{}: {}", name, e))?;
+            if let Kind::Grid = fk.kind {
+                out.push_str(&self.grid_section(field, &name)?);
+            } else if let Some(partial) = partial(&fk.kind) {
+                out.push_str(&format!("{{{{#{0}}}}} {{{{> html/{1} }}}} {{{{/{0}}}}}<br/>\n", name, partial));
+            }
+        }
+        Ok(out)
+    }
+
+    fn grid_section(&self, field: &Field, name: &str) -> Result<String, String> {
+        let mut cells = String::new();
+        match self.row_struct(field) {
+            Some(row) => {
+                for row_field in named_fields(row) {
+                    let row_name = row_field.ident.as_ref().unwrap().to_string();
+                    let fk = field_kind(row_field).map_err(|e| format!("{}: {}", row_name, e))?;
+                    // Buttons are not supported in the rows
+                    if let (Some(partial), false) = (partial(&fk.kind), matches!(fk.kind, Kind::Button)) {
+                        cells.push_str(&format!("<td>{{{{#{0}}}}}{{{{> html/{1}}}}}{{{{/{0}}}}}</td>", row_name, partial));
+                    }
+                }
+            }
+            None => cells.push_str("{{! the fields of the row }}"),
+        }
+        Ok(format!(
+            "<table>\n{{{{#{0}}}}}\n<tr>{1}<td>{{{{> html/row_remove}}}}</td></tr>\n{{{{/{0}}}}}\n</table>\n\
+             <input type=\"submit\" name=\"submit_{0}__add\" value=\"Add a line\"><br/>\n",
+            name, cells
+        ))
+    }
+
+    fn has_buttons(&self) -> bool {
+        named_fields(&self.state).any(|f| matches!(field_kind(f).map(|fk| fk.kind), Ok(Kind::Button)))
+    }
+
+    /// A new template for the page, with a submit button unless the state has its own buttons
+    pub fn template(&self, title: &str) -> Result<String, String> {
+        let submit = if self.has_buttons() {
+            ""
+        } else {
+            "<input type=\"submit\" name=\"submit_save\" value=\"Save\">\n"
+        };
+        Ok(format!(
+            "<h1>{}</h1>\n\
+             {{{{#state.message}}}}\n<font color=\"red\">{{{{state.message}}}}</font>\n{{{{/state.message}}}}\n\n\
+             <form method=\"post\">\n{}\n{}{}\n{}</form>\n",
+            title,
+            FIELDS_BEGIN,
+            self.fields_section()?,
+            FIELDS_END,
+            submit
+        ))
+    }
+}
+
+/// The partial rendering the element of the kind - a grid is a table of them
+fn partial(kind: &Kind) -> Option<&'static str> {
+    let partial = match kind {
+        Kind::Text => "text",
+        Kind::TextArea => "textarea",
+        Kind::Number => "number",
+        Kind::Date => "date",
+        Kind::Email => "email",
+        Kind::Url => "url",
+        Kind::Hidden => "hidden",
+        Kind::Check => "checkbox",
+        Kind::Select(_) => "select",
+        Kind::Radio(_) => "radio",
+        Kind::MultiSelect(_) => "multiselect",
+        Kind::Button => "submit",
+        Kind::Grid | Kind::Skip => return None,
+    };
+    Some(partial)
+}
+
+/// Replace the section between the markers in the template with the new one
+pub fn replace_fields_section(template: &str, section: &str) -> Result<String, String> {
+    let begin = template
+        .find(FIELDS_BEGIN)
+        .ok_or_else(|| format!("the template has no '{}' marker", FIELDS_BEGIN))?;
+    let end = template[begin..]
+        .find(FIELDS_END)
+        .map(|end| begin + end)
+        .ok_or_else(|| format!("the template has no '{}' marker", FIELDS_END))?;
+    Ok(format!(
+        "{}\n{}{}",
+        &template[..begin + FIELDS_BEGIN.len()],
+        section,
+        &template[end..]
+    ))
+}
+
+fn named_fields(item: &ItemStruct) -> impl Iterator<Item = &Field> {
+    let fields = match &item.fields {
+        Fields::Named(fields) => Some(fields.named.iter()),
+        _ => None,
+    };
+    fields.into_iter().flatten()
+}
+
+fn collect_structs(items: &[Item], structs: &mut Vec<ItemStruct>) {
+    for item in items {
+        match item {
+            Item::Struct(s) => structs.push(s.clone()),
+            Item::Mod(m) => {
+                if let Some((_, items)) = &m.content {
+                    collect_structs(items, structs);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether the struct has the RspState derive, under any of the names it is imported as
+fn derives_state(item: &ItemStruct) -> bool {
+    let mut found = false;
+    for attr in item.attrs.iter().filter(|a| a.path().is_ident("derive")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if let Some(last) = meta.path.segments.last() {
+                let name = last.ident.to_string();
+                found |= name == "RspState" || name == "RspStateDerive" || name == "DeriveRspState";
+            }
+            Ok(())
+        });
+    }
+    found
+}
+
+/// `Row` of a `Vec<Row>`
+fn vec_item_type(ty: &syn::Type) -> Option<String> {
+    let syn::Type::Path(path) = ty else {
+        return None;
+    };
+    let last = path.path.segments.last()?;
+    if last.ident != "Vec" {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(args) = &last.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        syn::GenericArgument::Type(syn::Type::Path(item)) => Some(item.path.segments.last()?.ident.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+        #[derive(Debug, Clone, Default, RspRow)]
+        pub struct OrderLine {
+            txtProduct: String,
+            numQty: i32,
+            btnDetails: bool,
+        }
+
+        #[derive(Debug, Clone, Default, RspState)]
+        pub struct PageState {
+            message: String,
+            txtName: String,
+            #[rsp(select = "colors")]
+            color: i32,
+            gridLines: Vec<OrderLine>,
+        }
+    "#;
+
+    #[test]
+    fn fields_section_has_the_elements() {
+        let source = RspPageSource::parse(SOURCE, None).unwrap();
+        assert_eq!(
+            source.fields_section().unwrap(),
+            "{{#txtName}} {{> html/text }} {{/txtName}}<br/>\n\
+             {{#color}} {{> html/select }} {{/color}}<br/>\n\
+             <table>\n{{#gridLines}}\n\
+             <tr><td>{{#txtProduct}}{{> html/text}}{{/txtProduct}}</td><td>{{#numQty}}{{> html/number}}{{/numQty}}</td>\
+             <td>{{> html/row_remove}}</td></tr>\n{{/gridLines}}\n</table>\n\
+             <input type=\"submit\" name=\"submit_gridLines__add\" value=\"Add a line\"><br/>\n"
+        );
+    }
+
+    #[test]
+    fn grid_without_its_row_struct_gets_a_placeholder() {
+        let source = RspPageSource::parse(
+            "#[derive(RspState)] struct PageState { gridLines: Vec<Missing> }",
+            None,
+        )
+        .unwrap();
+        assert!(source.fields_section().unwrap().contains("<tr>{{! the fields of the row }}<td>"));
+    }
+
+    #[test]
+    fn fields_section_is_replaced_between_the_markers() {
+        let template = format!("<h1>Page</h1>\n{}\nold\n{}\n<p>kept</p>\n", FIELDS_BEGIN, FIELDS_END);
+        assert_eq!(
+            replace_fields_section(&template, "new\n").unwrap(),
+            format!("<h1>Page</h1>\n{}\nnew\n{}\n<p>kept</p>\n", FIELDS_BEGIN, FIELDS_END)
+        );
+    }
+
+    #[test]
+    fn missing_markers_are_reported() {
+        assert!(replace_fields_section("<h1>Page</h1>", "new\n").is_err());
+        assert!(replace_fields_section(&format!("{}\nold\n", FIELDS_BEGIN), "new\n").is_err());
+        assert!(replace_fields_section(&format!("{}\n{}", FIELDS_END, FIELDS_BEGIN), "new\n").is_err());
+    }
+}
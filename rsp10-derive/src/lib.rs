@@ -2,6 +2,8 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::{parse_macro_input, DeriveInput, Data, Fields, Field};
 
+use rsp10_field_kind::{field_kind, FieldKind, Kind};
+
 /// Derive macro for RspKey trait implementation
 ///
 /// Automatically generates from_query_args() implementation based on struct fields:
@@ -37,6 +39,58 @@ pub fn derive_rsp_key(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+/// Derive macro for the RspRow trait, for the rows of an editable grid
+///
+/// The fields get their inputs by the same prefixes as with `#[derive(RspState)]`,
+/// except for buttons and nested grids, and can carry `#[rsp_validate(...)]` rules.
+#[proc_macro_derive(RspRow, attributes(rsp, rsp_source, rsp_validate))]
+pub fn derive_rsp_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => panic!("RspRow can only be derived for structs with named fields"),
+        },
+        _ => panic!("RspRow can only be derived for structs"),
+    };
+
+    let field_inputs = match generate_field_inputs(fields, true) {
+        Ok(f) => f,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let checks = match generate_validate_checks(fields, true) {
+        Ok(c) => c,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input_names = match input_field_names(fields) {
+        Ok(i) => i,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let expanded = quote! {
+        impl rsp10::RspRow for #name {
+            fn fill_row(ri: &mut rsp10::RspRowInfo<Self>, gd: &mut rsp10::RspRowData) -> bool {
+                let mut modified = false;
+                #(#field_inputs)*
+                modified
+            }
+
+            #[allow(unused_variables)]
+            fn validate_row(&self, row_prefix: &str, v: &mut rsp10::RspValidator) {
+                #(#checks)*
+            }
+
+            fn input_fields() -> &'static [&'static str] {
+                &[#(#input_names),*]
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
 /// Derive macro for RspState trait implementation
 ///
 /// Automatically generates fill_data() and related methods based on field naming conventions:
@@ -44,8 +98,22 @@ pub fn derive_rsp_key(input: TokenStream) -> TokenStream {
 /// - ddXXX: Dropdown/select element
 /// - cbXXX: Checkbox
 /// - rbXXX: Radio button group
+/// - msXXX: Multi-select, for a Vec field
+/// - taXXX: Textarea
+/// - numXXX: Number input
+/// - dtXXX: Date (NaiveDate) or date and time (NaiveDateTime) picker
+/// - emXXX: Email input
+/// - urlXXX: URL input
+/// - hidXXX: Hidden input
+/// - gridXXX: Editable grid, a Vec of a `#[derive(RspRow)]` struct
 /// - Other: Plain data
-#[proc_macro_derive(RspState, attributes(rsp_source, rsp_key, rsp_auth, rsp_template))]
+///
+/// An `#[rsp(...)]` attribute sets the kind explicitly instead, so that the fields can
+/// have normal names - `#[rsp(text, label = "Name", placeholder = "...", disabled)]`,
+/// `#[rsp(select = "get_items")]`, `#[rsp(skip)]` and so on, see the `rsp10-field-kind` crate.
+///
+/// Fields can carry validation rules, see `generate_validate`.
+#[proc_macro_derive(RspState, attributes(rsp, rsp_source, rsp_key, rsp_auth, rsp_template, rsp_layout, rsp_content_type, rsp_validate))]
 pub fn derive_rsp_state(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
@@ -66,7 +134,41 @@ pub fn derive_rsp_state(input: TokenStream) -> TokenStream {
     };
 
     // Generate fill_data implementation
-    let fill_data_impl = generate_fill_data(fields);
+    let fill_data_impl = match generate_fill_data(fields) {
+        Ok(f) => f,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    // Generate the RspStateMeta implementation
+    let validate_impl = match generate_validate(fields) {
+        Ok(v) => v,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let grid_event_impl = match generate_grid_event(fields) {
+        Ok(g) => g,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let page_config_impl = match generate_page_config(&input.attrs) {
+        Ok(c) => c,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let template_names_impl = match generate_template_names(fields) {
+        Ok(t) => t,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let input_fields_impl = match generate_input_fields(fields) {
+        Ok(i) => i,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let meta_impl = quote! {
+        impl rsp10::RspStateMeta for #name {
+            #validate_impl
+            #grid_event_impl
+            #page_config_impl
+            #template_names_impl
+            #input_fields_impl
+        }
+    };
 
     // TODO: Extract these from attributes
     // For now, we'll leave them as associated types/generics
@@ -89,11 +191,12 @@ pub fn derive_rsp_state(input: TokenStream) -> TokenStream {
             // Axum handler (when axum feature is enabled) - State must come first for Handler trait
             #[cfg(feature = "axum")]
             pub async fn axum_handler(
-                state: axum::extract::State<std::sync::Arc<tokio::sync::Mutex<rsp10::axum_adapter::SessionData>>>,
-                query: axum::extract::Query<std::collections::HashMap<String, String>>,
-                form: Option<axum::extract::Form<std::collections::HashMap<String, String>>>,
+                state: axum::extract::State<rsp10::axum_adapter::SessionStore>,
+                parts: axum::http::request::Parts,
+                query: axum::extract::Query<Vec<(String, String)>>,
+                form: Option<axum::extract::Form<Vec<(String, String)>>>,
             ) -> axum::response::Response {
-                rsp10::axum_adapter::axum_handler_fn::<#name, #key_ty, #auth_ty>((query, form, state)).await
+                rsp10::axum_adapter::axum_handler_fn::<#name, #key_ty, #auth_ty>((query, form, state, parts)).await
             }
 
             // Unified web handler - returns framework-specific handler
@@ -112,6 +215,8 @@ pub fn derive_rsp_state(input: TokenStream) -> TokenStream {
                     <Self as rsp10::RspState<#key_ty, #auth_ty>>::fill_data_result(ri, gd)
                 }
             }
+
+            #meta_impl
         }
     } else {
         // Generate with generic types (fallback)
@@ -130,81 +235,340 @@ pub fn derive_rsp_state(input: TokenStream) -> TokenStream {
                     Self::fill_data_result(ri, gd)
                 }
             }
+
+            #meta_impl
         }
     };
 
     TokenStream::from(expanded)
 }
 
-fn generate_fill_data(fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>) -> proc_macro2::TokenStream {
+fn generate_fill_data(fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>) -> syn::Result<proc_macro2::TokenStream> {
+    let field_generations = generate_field_inputs(fields, false)?;
+
+    Ok(quote! {
+        #(#field_generations)*
+        rsp10_data!(modified => gd);
+    })
+}
+
+/// The element macro calls for the fields, by their kinds. Grids do not nest,
+/// and a button in a row could not tell which row it was pressed in.
+fn generate_field_inputs(fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>, in_row: bool) -> syn::Result<Vec<proc_macro2::TokenStream>> {
     let mut field_generations = Vec::new();
 
     for field in fields {
         let field_name = field.ident.as_ref().unwrap();
-        let field_name_str = field_name.to_string();
-
-        // Determine field type based on prefix
-        let field_gen = if field_name_str.starts_with("txt") {
-            generate_text_field(field_name)
-        } else if field_name_str.starts_with("dd") {
-            generate_dropdown_field(field_name, field)
-        } else if field_name_str.starts_with("cb") {
-            generate_checkbox_field(field_name)
-        } else if field_name_str.starts_with("rb") {
-            generate_radio_field(field_name)
-        } else if field_name_str.starts_with("btn") {
-            generate_button_field(field_name)
-        } else {
-            // Plain data field
-            continue;
+        let fk = field_kind(field)?;
+
+        let field_gen = match fk.kind {
+            Kind::Text => quote! { rsp10_text!(#field_name, ri => gd, modified); },
+            Kind::TextArea => quote! { rsp10_textarea!(#field_name, ri => gd, modified); },
+            Kind::Number => quote! { rsp10_number!(#field_name, ri => gd, modified); },
+            Kind::Date => quote! { rsp10_date!(#field_name, ri => gd, modified); },
+            Kind::Email => quote! { rsp10_email!(#field_name, ri => gd, modified); },
+            Kind::Url => quote! { rsp10_url!(#field_name, ri => gd, modified); },
+            Kind::Hidden => quote! { rsp10_hidden!(#field_name, ri => gd); },
+            Kind::Check => quote! { rsp10_check!(#field_name, ri => gd, modified); },
+            Kind::Select(ref source) => {
+                let source_fn = items_source(field_name, field, source);
+                quote! { rsp10_select!(#field_name, #source_fn(ri.state.#field_name.clone()), ri => gd, modified); }
+            }
+            Kind::Radio(ref source) => {
+                let source_fn = items_source(field_name, field, source);
+                quote! { rsp10_radio!(#field_name, #source_fn(ri.state.#field_name.clone()), ri => gd, modified); }
+            }
+            Kind::MultiSelect(ref source) => {
+                let source_fn = items_source(field_name, field, source);
+                quote! { rsp10_multiselect!(#field_name, #source_fn(ri.state.#field_name.clone()), ri => gd, modified); }
+            }
+            Kind::Button | Kind::Grid if in_row => {
+                return Err(syn::Error::new(field_name.span(), "buttons and grids are not supported in a row"));
+            }
+            Kind::Button => generate_button_field(field_name, &fk),
+            Kind::Grid => quote! { rsp10_grid!(#field_name, ri => gd, modified); },
+            Kind::Skip => continue,
         };
 
         field_generations.push(field_gen);
+        field_generations.push(generate_field_options(field_name, &fk));
     }
 
+    Ok(field_generations)
+}
+
+/// Apply the label, placeholder and disabled options to the element made by the macro
+fn generate_field_options(field_name: &syn::Ident, fk: &FieldKind) -> proc_macro2::TokenStream {
+    let mut options = Vec::new();
+    if let (Some(label), false) = (&fk.label, matches!(fk.kind, Kind::Button)) {
+        options.push(quote! { e.labeltext = #label.to_string(); });
+    }
+    if let Some(placeholder) = &fk.placeholder {
+        options.push(quote! { e.placeholder = #placeholder.to_string(); });
+    }
+    if fk.disabled {
+        options.push(quote! { e.disabled = true; });
+    }
+    if options.is_empty() {
+        return quote! {};
+    }
     quote! {
-        #(#field_generations)*
-        rsp10_data!(modified => gd);
+        {
+            let mut e = #field_name.borrow_mut();
+            #(#options)*
+        }
     }
 }
 
-fn generate_text_field(field_name: &syn::Ident) -> proc_macro2::TokenStream {
-    quote! {
-        rsp10_text!(#field_name, ri => gd, modified);
+fn is_grid(field: &Field) -> syn::Result<bool> {
+    Ok(matches!(field_kind(field)?.kind, Kind::Grid))
+}
+
+/// Generate `RspStateMeta::grid_event` for the grid fields
+fn generate_grid_event(fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>) -> syn::Result<proc_macro2::TokenStream> {
+    let mut grids = Vec::new();
+    for field in fields {
+        if is_grid(field)? {
+            grids.push(field.ident.as_ref().unwrap());
+        }
     }
+    if grids.is_empty() {
+        return Ok(quote! {});
+    }
+    let grid_names: Vec<String> = grids.iter().map(|g| g.to_string()).collect();
+
+    Ok(quote! {
+        fn grid_event(&mut self, event: &rsp10::RspEvent) -> bool {
+            #(rsp10::grid::grid_event(&mut self.#grids, #grid_names, event))||*
+        }
+    })
 }
 
-fn generate_checkbox_field(field_name: &syn::Ident) -> proc_macro2::TokenStream {
-    quote! {
-        rsp10_check!(#field_name, ri => gd, modified);
+/// Generate `RspStateMeta::validate` from the `#[rsp_validate(...)]` field attributes:
+///
+/// - required: the field must be filled in / checked / selected
+/// - min = N, max = N: numeric bounds
+/// - length(min = N, max = N): bounds of the text length in characters
+/// - regex = "...": the text must match, the pattern is checked at compile time
+/// - custom = path::to::fn: `fn(&FieldType) -> Result<(), String>`
+/// - message = "...": replaces the default message of the rules in this attribute
+fn generate_validate(fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>) -> syn::Result<proc_macro2::TokenStream> {
+    let mut checks = generate_validate_checks(fields, false)?;
+
+    for field in fields {
+        let field_name = field.ident.as_ref().unwrap();
+        let field_name_str = field_name.to_string();
+        if is_grid(field)? {
+            checks.push(quote! {
+                rsp10::grid::validate_rows(&self.#field_name, #field_name_str, &mut v);
+            });
+        }
+    }
+
+    if checks.is_empty() {
+        return Ok(quote! {});
     }
+
+    Ok(quote! {
+        fn validate(&self) -> Vec<rsp10::RspFieldError> {
+            let mut v = rsp10::RspValidator::new();
+            #(#checks)*
+            v.into_errors()
+        }
+    })
 }
 
-fn generate_button_field(field_name: &syn::Ident) -> proc_macro2::TokenStream {
+/// The checks of the `#[rsp_validate(...)]` rules, with `v` being the RspValidator.
+/// In a row the errors are named after the field within the row, see `row_prefix`.
+fn generate_validate_checks(fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>, in_row: bool) -> syn::Result<Vec<proc_macro2::TokenStream>> {
+    let mut checks = Vec::new();
+
+    for field in fields {
+        let field_name = field.ident.as_ref().unwrap();
+        let field_name_lit = field_name.to_string();
+        let field_name_str = if in_row {
+            quote! { &format!("{}__{}", row_prefix, #field_name_lit) }
+        } else {
+            quote! { #field_name_lit }
+        };
+
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("rsp_validate")) {
+            let mut rules = Vec::new();
+            let mut message: Option<syn::LitStr> = None;
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("required") {
+                    rules.push(quote! { v.required(#field_name_str, &self.#field_name, message); });
+                } else if meta.path.is_ident("min") {
+                    let min: syn::Expr = meta.value()?.parse()?;
+                    rules.push(quote! { v.min(#field_name_str, &self.#field_name, (#min) as f64, message); });
+                } else if meta.path.is_ident("max") {
+                    let max: syn::Expr = meta.value()?.parse()?;
+                    rules.push(quote! { v.max(#field_name_str, &self.#field_name, (#max) as f64, message); });
+                } else if meta.path.is_ident("length") {
+                    let mut min = quote! { None };
+                    let mut max = quote! { None };
+                    meta.parse_nested_meta(|len_meta| {
+                        let bound: syn::Expr = len_meta.value()?.parse()?;
+                        if len_meta.path.is_ident("min") {
+                            min = quote! { Some((#bound) as usize) };
+                        } else if len_meta.path.is_ident("max") {
+                            max = quote! { Some((#bound) as usize) };
+                        } else {
+                            return Err(len_meta.error("expected length(min = N, max = N)"));
+                        }
+                        Ok(())
+                    })?;
+                    rules.push(quote! { v.length(#field_name_str, &self.#field_name, #min, #max, message); });
+                } else if meta.path.is_ident("regex") {
+                    let pattern: syn::LitStr = meta.value()?.parse()?;
+                    if let Err(e) = regex::Regex::new(&pattern.value()) {
+                        return Err(syn::Error::new(pattern.span(), format!("invalid regex: {}", e)));
+                    }
+                    rules.push(quote! { v.regex(#field_name_str, &self.#field_name, #pattern, message); });
+                } else if meta.path.is_ident("custom") {
+                    let check: syn::Path = meta.value()?.parse()?;
+                    rules.push(quote! { v.custom(#field_name_str, &self.#field_name, #check, message); });
+                } else if meta.path.is_ident("message") {
+                    message = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("unknown rsp_validate rule"));
+                }
+                Ok(())
+            })?;
+
+            let message = match message {
+                Some(m) => quote! { Some(#m) },
+                None => quote! { None },
+            };
+            checks.push(quote! {
+                {
+                    let message: Option<&str> = #message;
+                    #(#rules)*
+                }
+            });
+        }
+    }
+
+    Ok(checks)
+}
+
+fn generate_button_field(field_name: &syn::Ident, fk: &FieldKind) -> proc_macro2::TokenStream {
     let field_name_str = field_name.to_string();
-    let label = field_name_str
-        .strip_prefix("btn")
-        .unwrap_or(&field_name_str)
-        .to_string();
+    let label = match &fk.label {
+        Some(label) => label.value(),
+        None => field_name_str
+            .strip_prefix("btn")
+            .unwrap_or(&field_name_str)
+            .to_string(),
+    };
 
     quote! {
         rsp10_button!(#field_name, #label => gd);
     }
 }
 
-fn generate_radio_field(_field_name: &syn::Ident) -> proc_macro2::TokenStream {
-    // TODO: Implement radio button generation
-    quote! {
-        // Radio button not yet implemented
+/// The names the template of the page may use, for the template checker:
+/// the fields of the state, and the elements plus `modified` of `generate_fill_data`
+fn generate_template_names(fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>) -> syn::Result<proc_macro2::TokenStream> {
+    let mut field_names = Vec::new();
+    let mut element_names = vec!["modified".to_string()];
+    for field in fields {
+        let field_name = field.ident.as_ref().unwrap().to_string();
+        if !matches!(field_kind(field)?.kind, Kind::Skip) {
+            element_names.push(field_name.clone());
+        }
+        field_names.push(field_name);
     }
+    Ok(quote! {
+        fn template_fields() -> &'static [&'static str] {
+            &[#(#field_names),*]
+        }
+        fn template_elements() -> &'static [&'static str] {
+            &[#(#element_names),*]
+        }
+    })
 }
 
-fn generate_dropdown_field(field_name: &syn::Ident, field: &Field) -> proc_macro2::TokenStream {
-    let source_fn = get_dropdown_source(field_name, field);
+/// The fields with an enabled input element, which the posted form may amend
+fn input_field_names(fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>) -> syn::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for field in fields {
+        let fk = field_kind(field)?;
+        if !fk.disabled && !matches!(fk.kind, Kind::Skip | Kind::Button | Kind::Grid) {
+            names.push(field.ident.as_ref().unwrap().to_string());
+        }
+    }
+    Ok(names)
+}
 
-    quote! {
-        rsp10_select!(#field_name, #source_fn(ri.state.#field_name), ri => gd, modified);
+/// Generate `RspStateMeta::input_fields` and `grid_input_fields`, the latter
+/// taking the input fields of the rows from their `RspRow` impl
+fn generate_input_fields(fields: &syn::punctuated::Punctuated<Field, syn::token::Comma>) -> syn::Result<proc_macro2::TokenStream> {
+    let input_names = input_field_names(fields)?;
+    let mut grids = Vec::new();
+    for field in fields {
+        if is_grid(field)? {
+            grids.push(field.ident.as_ref().unwrap());
+        }
     }
+    let grid_names: Vec<String> = grids.iter().map(|g| g.to_string()).collect();
+
+    Ok(quote! {
+        fn input_fields() -> &'static [&'static str] {
+            &[#(#input_names),*]
+        }
+        fn grid_input_fields(grid: &str) -> &'static [&'static str] {
+            match grid {
+                #(#grid_names => rsp10::grid::row_input_fields(|s: &Self| &s.#grids),)*
+                _ => &[],
+            }
+        }
+    })
+}
+
+/// Template, layout and content type of the page, from the struct attributes:
+/// `#[rsp_template("path")]`, `#[rsp_layout("path")]` or `#[rsp_layout("path", slot = "name")]`,
+/// `#[rsp_content_type("type")]`
+fn generate_page_config(attrs: &[syn::Attribute]) -> syn::Result<proc_macro2::TokenStream> {
+    let mut config = Vec::new();
+    for (attr_name, method) in [
+        ("rsp_template", "template_name"),
+        ("rsp_layout", "layout_name"),
+        ("rsp_content_type", "content_type"),
+    ] {
+        if let Some(attr) = attrs.iter().find(|a| a.path().is_ident(attr_name)) {
+            let span = attr.path().get_ident().unwrap().span();
+            let (value, slot) = attr.parse_args_with(|input: syn::parse::ParseStream| {
+                let value: syn::LitStr = input.parse()?;
+                let mut slot: Option<syn::LitStr> = None;
+                if attr_name == "rsp_layout" && input.peek(syn::Token![,]) {
+                    input.parse::<syn::Token![,]>()?;
+                    let option: syn::Ident = input.parse()?;
+                    if option != "slot" {
+                        return Err(syn::Error::new(option.span(), "expected `slot = \"name\"`"));
+                    }
+                    input.parse::<syn::Token![=]>()?;
+                    slot = Some(input.parse()?);
+                }
+                Ok((value, slot))
+            })?;
+            let method = syn::Ident::new(method, span);
+            config.push(quote! {
+                fn #method() -> Option<&'static str> {
+                    Some(#value)
+                }
+            });
+            if let Some(slot) = slot {
+                config.push(quote! {
+                    fn layout_slot() -> Option<&'static str> {
+                        Some(#slot)
+                    }
+                });
+            }
+        }
+    }
+    Ok(quote! { #(#config)* })
 }
 
 fn extract_types_from_attrs(attrs: &[syn::Attribute]) -> (Option<syn::Type>, Option<syn::Type>) {
@@ -226,7 +590,11 @@ fn extract_types_from_attrs(attrs: &[syn::Attribute]) -> (Option<syn::Type>, Opt
     (key_type, auth_type)
 }
 
-fn get_dropdown_source(field_name: &syn::Ident, field: &Field) -> proc_macro2::TokenStream {
+/// The function returning the items of a dropdown, a multi-select or a radio button group
+fn items_source(field_name: &syn::Ident, field: &Field, source: &Option<syn::Path>) -> proc_macro2::TokenStream {
+    if let Some(source) = source {
+        return quote! { #source };
+    }
     // Check for explicit #[rsp_source(func_name)] attribute
     for attr in &field.attrs {
         if attr.path().is_ident("rsp_source") {
@@ -336,3 +704,59 @@ fn generate_from_query_args(fields: &syn::punctuated::Punctuated<Field, syn::tok
         }
     }
 }
+
+/// The templates under the directory, embedded into the binary:
+/// `embed_templates!("templates")` gives `&[("teststate", "<html>..."), ("html/text", ...), ...]`.
+/// The `.mustache` files by default, `embed_templates!("templates", "jinja")` for the `.jinja` ones.
+///
+/// The directory is relative to the Cargo.toml of the crate. The files are included
+/// with `include_str!`, so a changed template rebuilds the crate - a new one does not.
+#[proc_macro]
+pub fn embed_templates(input: TokenStream) -> TokenStream {
+    let parser = syn::punctuated::Punctuated::<syn::LitStr, syn::Token![,]>::parse_separated_nonempty;
+    let args = parse_macro_input!(input with parser);
+    let dir = &args[0];
+    if args.len() > 2 {
+        return syn::Error::new(args[2].span(), "expected the directory and the extension")
+            .to_compile_error()
+            .into();
+    }
+    let extension = format!(".{}", args.get(1).map(|e| e.value()).unwrap_or_else(|| "mustache".to_string()));
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    let root = std::path::Path::new(&manifest_dir).join(dir.value());
+
+    let mut files = Vec::new();
+    if let Err(e) = collect_templates(&root, "", &extension, &mut files) {
+        let msg = format!("can not read the templates in {}: {}", root.display(), e);
+        return syn::Error::new(dir.span(), msg).to_compile_error().into();
+    }
+    let entries = files.iter().map(|(name, path)| {
+        quote! { (#name, include_str!(#path)) }
+    });
+    TokenStream::from(quote! {
+        {
+            let templates: &'static [(&'static str, &'static str)] = &[#(#entries),*];
+            templates
+        }
+    })
+}
+
+/// The `(name, absolute path)` of the template files under the directory
+fn collect_templates(dir: &std::path::Path, prefix: &str, extension: &str, files: &mut Vec<(String, String)>) -> std::io::Result<()> {
+    let mut entries: Vec<std::path::PathBuf> = std::fs::read_dir(dir)?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if path.is_dir() {
+            collect_templates(&path, &format!("{}{}/", prefix, file_name), extension, files)?;
+        } else if let Some(name) = file_name.strip_suffix(extension) {
+            let path = path.canonicalize()?;
+            files.push((format!("{}{}", prefix, name), path.to_string_lossy().to_string()));
+        }
+    }
+    Ok(())
+}
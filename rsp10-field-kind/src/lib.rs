@@ -0,0 +1,250 @@
+//! How a field of the state is rendered
+//!
+//! The kind comes from the `#[rsp(...)]` attribute of the field:
+//!
+//! ```ignore
+//! #[rsp(text, label = "Name", placeholder = "Your name")]
+//! user_name: String,
+//! #[rsp(select = "get_colors", disabled)]
+//! color: i32,
+//! #[rsp(skip)]
+//! txt_not_an_input: String,
+//! ```
+//!
+//! Without the attribute, the prefix of the field name decides, see `derive_rsp_state`.
+//! The derive and the `rsp10` command line tool both pick the elements this way.
+
+use syn::{Field, LitStr, Path};
+
+pub enum Kind {
+    Text,
+    TextArea,
+    Number,
+    Date,
+    Email,
+    Url,
+    Hidden,
+    Check,
+    /// The function returning the items, if not the default one
+    Select(Option<Path>),
+    Radio(Option<Path>),
+    MultiSelect(Option<Path>),
+    Button,
+    Grid,
+    /// Plain data, no element
+    Skip,
+}
+
+pub struct FieldKind {
+    pub kind: Kind,
+    pub label: Option<LitStr>,
+    pub placeholder: Option<LitStr>,
+    pub disabled: bool,
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Text => "text",
+            Kind::TextArea => "textarea",
+            Kind::Number => "number",
+            Kind::Date => "date",
+            Kind::Email => "email",
+            Kind::Url => "url",
+            Kind::Hidden => "hidden",
+            Kind::Check => "check",
+            Kind::Select(_) => "select",
+            Kind::Radio(_) => "radio",
+            Kind::MultiSelect(_) => "multiselect",
+            Kind::Button => "button",
+            Kind::Grid => "grid",
+            Kind::Skip => "skip",
+        }
+    }
+
+    fn has_label(&self) -> bool {
+        !matches!(self, Kind::Hidden | Kind::Grid | Kind::Skip)
+    }
+
+    fn has_placeholder(&self) -> bool {
+        matches!(self, Kind::Text | Kind::TextArea | Kind::Number | Kind::Email | Kind::Url)
+    }
+
+    fn has_disabled(&self) -> bool {
+        !matches!(self, Kind::Hidden | Kind::Grid | Kind::Skip)
+    }
+}
+
+/// The kind of the field, from its attribute or its name
+pub fn field_kind(field: &Field) -> syn::Result<FieldKind> {
+    let mut kind: Option<Kind> = None;
+    let mut label = None;
+    let mut placeholder = None;
+    let mut disabled = false;
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("rsp")) {
+        attr.parse_nested_meta(|meta| {
+            let new_kind = if meta.path.is_ident("text") {
+                Kind::Text
+            } else if meta.path.is_ident("textarea") {
+                Kind::TextArea
+            } else if meta.path.is_ident("number") {
+                Kind::Number
+            } else if meta.path.is_ident("date") {
+                Kind::Date
+            } else if meta.path.is_ident("email") {
+                Kind::Email
+            } else if meta.path.is_ident("url") {
+                Kind::Url
+            } else if meta.path.is_ident("hidden") {
+                Kind::Hidden
+            } else if meta.path.is_ident("check") {
+                Kind::Check
+            } else if meta.path.is_ident("select") {
+                Kind::Select(source_path(&meta)?)
+            } else if meta.path.is_ident("radio") {
+                Kind::Radio(source_path(&meta)?)
+            } else if meta.path.is_ident("multiselect") {
+                Kind::MultiSelect(source_path(&meta)?)
+            } else if meta.path.is_ident("button") {
+                Kind::Button
+            } else if meta.path.is_ident("grid") {
+                Kind::Grid
+            } else if meta.path.is_ident("skip") {
+                Kind::Skip
+            } else if meta.path.is_ident("label") {
+                label = Some(meta.value()?.parse()?);
+                return Ok(());
+            } else if meta.path.is_ident("placeholder") {
+                placeholder = Some(meta.value()?.parse()?);
+                return Ok(());
+            } else if meta.path.is_ident("disabled") {
+                disabled = true;
+                return Ok(());
+            } else {
+                return Err(meta.error("unknown rsp option"));
+            };
+            if kind.is_some() {
+                return Err(meta.error("the field already has a kind"));
+            }
+            kind = Some(new_kind);
+            Ok(())
+        })?;
+    }
+
+    let field_name = field.ident.as_ref().unwrap();
+    let kind = kind.unwrap_or_else(|| kind_from_prefix(&field_name.to_string()));
+
+    let unsupported = if label.is_some() && !kind.has_label() {
+        Some("label")
+    } else if placeholder.is_some() && !kind.has_placeholder() {
+        Some("placeholder")
+    } else if disabled && !kind.has_disabled() {
+        Some("disabled")
+    } else {
+        None
+    };
+    if let Some(option) = unsupported {
+        return Err(syn::Error::new(
+            field_name.span(),
+            format!("{} is not supported for a {} field", option, kind.name()),
+        ));
+    }
+
+    Ok(FieldKind {
+        kind,
+        label,
+        placeholder,
+        disabled,
+    })
+}
+
+/// `select` alone uses the default source, `select = "fn"` or `select = path::to::fn` the given one
+fn source_path(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Option<Path>> {
+    if !meta.input.peek(syn::Token![=]) {
+        return Ok(None);
+    }
+    let value = meta.value()?;
+    if value.peek(LitStr) {
+        let lit: LitStr = value.parse()?;
+        Ok(Some(lit.parse()?))
+    } else {
+        Ok(Some(value.parse()?))
+    }
+}
+
+/// The naming convention, for the fields without an `#[rsp(...)]` kind
+fn kind_from_prefix(name: &str) -> Kind {
+    if name.starts_with("txt") {
+        Kind::Text
+    } else if name.starts_with("dd") {
+        Kind::Select(None)
+    } else if name.starts_with("cb") {
+        Kind::Check
+    } else if name.starts_with("rb") {
+        Kind::Radio(None)
+    } else if name.starts_with("btn") {
+        Kind::Button
+    } else if has_prefix(name, "grid") {
+        Kind::Grid
+    } else if has_prefix(name, "ms") {
+        Kind::MultiSelect(None)
+    } else if has_prefix(name, "ta") {
+        Kind::TextArea
+    } else if has_prefix(name, "num") {
+        Kind::Number
+    } else if has_prefix(name, "dt") {
+        Kind::Date
+    } else if has_prefix(name, "em") {
+        Kind::Email
+    } else if has_prefix(name, "url") {
+        Kind::Url
+    } else if has_prefix(name, "hid") {
+        Kind::Hidden
+    } else {
+        Kind::Skip
+    }
+}
+
+/// The newer, shorter prefixes only count when followed by an uppercase letter, so that
+/// e.g. `num_rows` or `email_sent` stay plain data - such a field needs an `#[rsp(...)]` kind
+fn has_prefix(field_name: &str, prefix: &str) -> bool {
+    match field_name.strip_prefix(prefix).and_then(|rest| rest.chars().next()) {
+        Some(c) => c.is_uppercase(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind_name(name: &str) -> &'static str {
+        kind_from_prefix(name).name()
+    }
+
+    #[test]
+    fn prefix_followed_by_uppercase_makes_an_input() {
+        assert_eq!(kind_name("numRows"), "number");
+        assert_eq!(kind_name("dtCreated"), "date");
+        assert_eq!(kind_name("hidOwner"), "hidden");
+        assert_eq!(kind_name("msTags"), "multiselect");
+    }
+
+    #[test]
+    fn prefix_followed_by_underscore_stays_plain_data() {
+        for name in ["num_rows", "dt_created", "hid_owner", "ms_total", "em_sent", "grid_size", "number_of_rows", "num"] {
+            assert_eq!(kind_name(name), "skip", "{}", name);
+        }
+    }
+
+    #[test]
+    fn attribute_makes_an_input_of_any_name() {
+        let input: syn::DeriveInput = syn::parse_str("struct S { #[rsp(number)] num_rows: i32 }").unwrap();
+        let field = match input.data {
+            syn::Data::Struct(data) => data.fields.into_iter().next().unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!(field_kind(&field).unwrap().kind.name(), "number");
+    }
+}
@@ -15,6 +15,15 @@ mod simple_pages;
 fn main() {
     dotenv::dotenv().ok();
 
+    rsp10::set_default_layout("layout");
+    if std::env::args().any(|a| a == "--check-templates") {
+        let problems = simple_pages::check_templates();
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        std::process::exit(if problems.is_empty() { 0 } else { 1 });
+    }
+
     let router = simple_pages::get_router();
     let mut s = rsp10::RspServer::new();
     // s.run(router, "Simple Example", 4480);
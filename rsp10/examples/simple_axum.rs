@@ -8,8 +8,17 @@ async fn main() {
     dotenv::dotenv().ok();
     env_logger::init();
 
+    rsp10::set_default_layout("layout");
+    if std::env::args().any(|a| a == "--check-templates") {
+        let problems = simple_pages::check_templates();
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        std::process::exit(if problems.is_empty() { 0 } else { 1 });
+    }
+
     let server = rsp10::axum_adapter::RspAxumServer::new();
-    let router = simple_pages::get_axum_router(server.session_data());
+    let router = simple_pages::get_axum_router(server.session_store());
     server.run(router, "Simple Example", 4480).await;
 }
 
@@ -10,12 +10,22 @@ pub struct LoginKey {
 #[rsp_key(LoginKey)]
 #[rsp_auth(NoPageAuth)]
 pub struct PageState {
+    #[rsp_validate(required, message = "Please enter the username")]
     txtUsername: String,
     txtPassword: String,
     message: Option<String>,
     return_url: String,
 }
 
+/// Only a path on this site is followed after the login, not an URL elsewhere
+fn local_url(url: String) -> String {
+    if url.starts_with('/') && !url.starts_with("//") && !url.starts_with("/\\") {
+        url
+    } else {
+        "/".to_string()
+    }
+}
+
 // Type alias removed - RspInfo now has only one lifetime
 
 impl RspState<LoginKey, MyPageAuth> for PageState {
@@ -24,10 +34,14 @@ impl RspState<LoginKey, MyPageAuth> for PageState {
             txtUsername: "".to_string(),
             txtPassword: "".to_string(),
             message: None,
-            return_url: if key.return_url.len() == 0 { "/".to_string() } else { key.return_url },
+            return_url: local_url(key.return_url),
         }
     }
 
+    fn get_template_data_names() -> Vec<String> {
+        vec!["env_username".to_string(), "env_password".to_string()]
+    }
+
     fn fill_data<'a>(ri: RspInfo<'a, Self, LoginKey, MyPageAuth>) -> RspFillDataResult<Self> {
         let mut modified = false;
         let mut gd = RspDataBuilder::new();
@@ -50,7 +64,7 @@ impl RspState<LoginKey, MyPageAuth> for PageState {
         println!("Debug - initial_state: {:?}", initial_state);
         println!("Debug - state: {:?}", state);
 
-        if ri.event.event == "submit" {
+        if ri.event.event == "submit" && ri.is_valid() {
             println!("Submit on login page");
             println!("Debug - received username: '{}'", state.txtUsername);
             println!("Debug - received password: '{}'", state.txtPassword);
@@ -72,7 +86,7 @@ impl RspState<LoginKey, MyPageAuth> for PageState {
                     initial_state,
                     state,
                     action,
-                    new_auth: Some(Box::new(auth)),
+                    new_auth: Some(RspNewAuth::new(auth)),
                 };
             } else {
                 println!("Login failure");
@@ -1,8 +1,5 @@
 #![allow(non_snake_case)]
 use super::imports::*;
-// use iron::Plugin;
-use iron::Plugin;
-use persistent::State;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default, RspKey)]
 pub struct SleepKey {
@@ -24,16 +21,10 @@ impl RspState<SleepKey, MyPageAuth> for PageState {
         }
     }
     fn fill_data(ri: RspInfo<Self, SleepKey, MyPageAuth>) -> RspFillDataResult<Self> {
-        use std::{thread, time};
+        // Rendering the page stops the server, whichever framework it runs on
+        rsp10::request_server_stop();
 
-        let num_sec = 60;
-        // println!("Sleeping for {} seconds...", num_sec);
-        //
-        // TODO: request_stop needs Iron-specific access
-        // rsp10::request_stop(ri.req);
-
-        let mut modified = false;
-        let mut gd = RspDataBuilder::new();
+        let gd = RspDataBuilder::new();
 
         Self::fill_data_result(ri, gd)
     }
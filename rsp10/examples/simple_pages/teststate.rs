@@ -10,6 +10,7 @@ pub struct KeyI32 {
 #[derive(Debug, Clone, Serialize, Deserialize, Default, RspStateDerive)]
 #[rsp_key(KeyI32)]
 #[rsp_auth(CookiePageAuth)]
+#[rsp_template("teststate")]
 pub struct PageState {
     message: String,
     dd_testing: i32,
@@ -49,9 +50,6 @@ pub fn dbh_get_testing_dropdown(_switchtype: i32) -> HtmlSelect<i32> {
 }
 
 impl RspState<KeyI32, MyPageAuth> for PageState {
-    fn get_template_name() -> String {
-        "teststate".to_string()
-    }
     fn get_state(auth: &MyPageAuth, key: KeyI32) -> PageState {
         println!("default state for PageState with key: {:?}", &key);
         PageState {
@@ -63,6 +61,15 @@ impl RspState<KeyI32, MyPageAuth> for PageState {
         }
     }
 
+    fn try_get_state(_ctx: &RspRequestContext, auth: &MyPageAuth, key: KeyI32) -> HttpResult<PageState> {
+        if let Some(id) = key.id {
+            if !(1..23).contains(&id) {
+                return Err(HttpError::NotFound(format!("There is no item {}", id)));
+            }
+        }
+        Ok(Self::get_state(auth, key))
+    }
+
     fn fill_data<'a>(ri: RspInfo<'a, Self, KeyI32, MyPageAuth>) -> RspFillDataResult<Self> {
         Self::derive_auto_fill_data_impl(ri)
     }
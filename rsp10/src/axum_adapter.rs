@@ -9,8 +9,8 @@ use std::sync::Arc;
 #[cfg(feature = "axum")]
 use axum::{
     extract::{Query, Form, State as AxumState},
-    http::{StatusCode, header},
-    response::{Html, Response, IntoResponse},
+    http::{StatusCode, HeaderMap, header},
+    response::{Response, IntoResponse},
     body::Body,
 };
 #[cfg(feature = "axum")]
@@ -21,18 +21,198 @@ use tower::ServiceBuilder;
 use tower_http::services::ServeDir;
 
 #[cfg(feature = "axum")]
-use crate::http_adapter::{HttpRequest, HttpResponse, HttpResult, HttpError};
+use crate::http_adapter::{HttpRequest, HttpResponse, HttpResult, HttpError, RspSessionValue, RspRequestContext, RspCookie};
 #[cfg(feature = "axum")]
-use crate::core::{RspState, RspUserAuth, RspEvent, RspAction, extract_event, extract_json_state, amend_json_value};
+use crate::core::{RspState, RspUserAuth, PageEngine};
 #[cfg(feature = "axum")]
 use crate::core::RspKey;
+
+#[cfg(feature = "axum")]
+const SESSION_COOKIE: &str = "rsp10_session";
+#[cfg(feature = "axum")]
+/// Seconds a session lasts without being used
+const SESSION_MAX_IDLE: u64 = 12 * 3600;
+
+#[cfg(feature = "axum")]
+/// Where the per-client session values are kept, and how the session cookie is sent
+#[derive(Clone)]
+pub struct SessionStore {
+    storage: SessionStorage,
+    secure: bool,
+    same_site: String,
+}
+
+#[cfg(feature = "axum")]
+#[derive(Clone)]
+enum SessionStorage {
+    /// In a cookie, encrypted with the server secret - the server stays stateless
+    EncryptedCookie,
+    /// On the server, keyed by a random session ID which is sent as the cookie
+    Server(Arc<std::sync::Mutex<HashMap<String, ServerSession>>>),
+}
+
+#[cfg(feature = "axum")]
+/// A session kept on the server
+pub struct ServerSession {
+    values: HashMap<String, String>,
+    last_seen: std::time::Instant,
+}
+
+#[cfg(feature = "axum")]
+impl ServerSession {
+    fn new(values: HashMap<String, String>) -> Self {
+        ServerSession {
+            values,
+            last_seen: std::time::Instant::now(),
+        }
+    }
+
+    fn expired(&self) -> bool {
+        self.last_seen.elapsed().as_secs() >= SESSION_MAX_IDLE
+    }
+}
+
 #[cfg(feature = "axum")]
-use crate::{maybe_compile_template};
+/// The session values sealed into the cookie, with the time they expire -
+/// the cookie itself can be kept by the client as long as it likes
+#[derive(Serialize, Deserialize)]
+struct SealedSession {
+    expires: u64,
+    values: HashMap<String, String>,
+}
+
+#[cfg(feature = "axum")]
+impl SessionStore {
+    pub fn encrypted_cookie() -> Self {
+        SessionStore::with_storage(SessionStorage::EncryptedCookie)
+    }
+
+    pub fn server() -> Self {
+        SessionStore::with_storage(SessionStorage::Server(Arc::new(std::sync::Mutex::new(HashMap::new()))))
+    }
+
+    fn with_storage(storage: SessionStorage) -> Self {
+        SessionStore {
+            storage,
+            secure: false,
+            same_site: "Lax".to_string(),
+        }
+    }
+
+    /// Send the session cookie only over HTTPS
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// SameSite of the session cookie: "Strict", "Lax" (the default) or "None"
+    pub fn same_site(mut self, same_site: &str) -> Self {
+        self.same_site = same_site.to_string();
+        self
+    }
+
+    fn cookie(&self, value: &str) -> RspCookie {
+        RspCookie::new(SESSION_COOKIE, value)
+            .path("/")
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(&self.same_site)
+    }
+
+    /// Load the session values for the session cookie, along with the
+    /// session ID if the session is kept on the server, and whether the
+    /// cookie has to be sent again to keep the session from expiring
+    fn load(&self, cookie: Option<&String>) -> (Option<String>, HashMap<String, String>, bool) {
+        match &self.storage {
+            SessionStorage::EncryptedCookie => {
+                let sealed: Option<SealedSession> = cookie
+                    .and_then(|c| crate::state_codec::open_state(SESSION_COOKIE, c))
+                    .and_then(|json| serde_json::from_str(&json).ok());
+                let now = crate::state_codec::unix_time();
+                match sealed {
+                    Some(sealed) if sealed.expires > now => {
+                        let refresh = sealed.expires - now < SESSION_MAX_IDLE / 2;
+                        (None, sealed.values, refresh)
+                    }
+                    _ => (None, HashMap::new(), false),
+                }
+            }
+            SessionStorage::Server(sessions) => {
+                let mut sessions = sessions.lock().unwrap();
+                // Unknown IDs are not reused, to avoid session fixation
+                match cookie.and_then(|id| sessions.get_mut(id).map(|session| (id, session))) {
+                    Some((id, session)) if !session.expired() => {
+                        session.last_seen = std::time::Instant::now();
+                        (Some(id.clone()), session.values.clone(), false)
+                    }
+                    Some((id, _)) => {
+                        let id = id.clone();
+                        sessions.remove(&id);
+                        (None, HashMap::new(), false)
+                    }
+                    None => (None, HashMap::new(), false),
+                }
+            }
+        }
+    }
+
+    /// Store the session values and return the Set-Cookie header value, if one is needed.
+    /// With `renew` a session kept on the server gets a new ID, so that an ID known
+    /// before e.g. the login can not be used to take the session over.
+    fn save(&self, session_id: Option<String>, values: &HashMap<String, String>, renew: bool) -> Option<String> {
+        let expired = self.cookie("").max_age(0).to_string();
+        match &self.storage {
+            SessionStorage::EncryptedCookie => {
+                if values.is_empty() {
+                    return Some(expired);
+                }
+                let sealed = SealedSession {
+                    expires: crate::state_codec::unix_time() + SESSION_MAX_IDLE,
+                    values: values.clone(),
+                };
+                let json = serde_json::to_string(&sealed).ok()?;
+                let sealed = crate::state_codec::seal_state(SESSION_COOKIE, &json);
+                Some(self.cookie(&sealed).to_string())
+            }
+            SessionStorage::Server(sessions) => {
+                let mut sessions = sessions.lock().unwrap();
+                if values.is_empty() {
+                    if let Some(id) = session_id {
+                        sessions.remove(&id);
+                        return Some(expired);
+                    }
+                    return None;
+                }
+                match session_id {
+                    Some(id) if !renew => {
+                        // The session may have been renewed or cleared by a concurrent
+                        // request meanwhile - the old ID must not come back
+                        if let Some(session) = sessions.get_mut(&id) {
+                            session.values = values.clone();
+                        }
+                        None
+                    }
+                    old_id => {
+                        if let Some(old_id) = old_id {
+                            sessions.remove(&old_id);
+                        }
+                        // Drop the sessions that were left behind, before adding one
+                        sessions.retain(|_, session| !session.expired());
+                        let id = new_session_id();
+                        sessions.insert(id.clone(), ServerSession::new(values.clone()));
+                        Some(self.cookie(&id).to_string())
+                    }
+                }
+            }
+        }
+    }
+}
 
 #[cfg(feature = "axum")]
-#[derive(Debug, Clone, Default)]
-pub struct SessionData {
-    pub auth_json: Option<String>, // Serialized auth data as JSON
+fn new_session_id() -> String {
+    use base64::Engine;
+    let bytes: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
 }
 
 #[cfg(feature = "axum")]
@@ -40,32 +220,79 @@ pub struct SessionData {
 pub struct AxumRequestAdapter {
     pub query_params: HashMap<String, Vec<String>>,
     pub form_data: HashMap<String, Vec<String>>,
-    pub session: Arc<tokio::sync::Mutex<SessionData>>,
+    pub headers: HeaderMap,
+    context: RspRequestContext,
+    store: SessionStore,
+    session_id: Option<String>,
+    session: HashMap<String, String>,
+    session_changed: bool,
+    /// A session value changed, e.g. the auth on login - the session gets a new ID
+    session_renew: bool,
 }
 
 #[cfg(feature = "axum")]
 impl AxumRequestAdapter {
     pub fn new(
-        query: Query<HashMap<String, String>>,
-        form: Option<Form<HashMap<String, String>>>,
-        session: Arc<tokio::sync::Mutex<SessionData>>,
+        parts: axum::http::request::Parts,
+        query: Query<Vec<(String, String)>>,
+        form: Option<Form<Vec<(String, String)>>>,
+        store: SessionStore,
     ) -> Self {
-        let mut query_params = HashMap::new();
+        // Keys may repeat, e.g. for a <select multiple>
+        let mut query_params: HashMap<String, Vec<String>> = HashMap::new();
         for (key, value) in query.0 {
-            query_params.insert(key, vec![value]);
+            query_params.entry(key).or_default().push(value);
+        }
+
+        let mut form_data: HashMap<String, Vec<String>> = HashMap::new();
+        if let Some(Form(form_pairs)) = form {
+            for (key, value) in form_pairs {
+                form_data.entry(key).or_default().push(value);
+            }
         }
 
-        let mut form_data = HashMap::new();
-        if let Some(Form(form_map)) = form {
-            for (key, value) in form_map {
-                form_data.insert(key, vec![value]);
+        // The path as requested, even if the router is nested
+        let uri = parts
+            .extensions
+            .get::<axum::extract::OriginalUri>()
+            .map(|original| original.0.clone())
+            .unwrap_or_else(|| parts.uri.clone());
+        let mut context = RspRequestContext {
+            method: parts.method.to_string(),
+            path: uri.path().to_string(),
+            query: uri.query().unwrap_or_default().to_string(),
+            remote_addr: parts
+                .extensions
+                .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+                .map(|info| info.0),
+            ..Default::default()
+        };
+        for (name, value) in parts.headers.iter() {
+            if let Ok(value) = value.to_str() {
+                context.add_header(name.as_str(), value);
             }
         }
+        let (session_id, session, refresh) = store.load(context.cookies.get(SESSION_COOKIE));
 
         Self {
             query_params,
             form_data,
+            headers: parts.headers,
+            context,
+            store,
+            session_id,
             session,
+            session_changed: refresh,
+            session_renew: false,
+        }
+    }
+
+    /// Persist the session changes, returning the Set-Cookie header value if one is needed
+    pub fn save_session(self) -> Option<String> {
+        if self.session_changed {
+            self.store.save(self.session_id, &self.session, self.session_renew)
+        } else {
+            None
         }
     }
 }
@@ -80,15 +307,26 @@ impl HttpRequest for AxumRequestAdapter {
         Ok(self.form_data.clone())
     }
 
-    fn get_session<T: 'static>(&mut self) -> Option<&T> {
-        // For simplicity, we'll store session data as a single type for now
-        // In a more complete implementation, this would use a type map
-        None
+    fn get_session_raw(&mut self, key: &str) -> Option<String> {
+        self.session.get(key).cloned()
+    }
+
+    fn set_session_raw(&mut self, key: &str, value: String) {
+        if self.session.get(key) != Some(&value) {
+            self.session_renew = true;
+        }
+        self.session.insert(key.to_string(), value);
+        self.session_changed = true;
     }
 
-    fn set_session<T: 'static>(&mut self, value: T) {
-        // For simplicity, we'll store session data as a single type for now
-        // In a more complete implementation, this would use a type map
+    fn clear_session(&mut self) {
+        // An empty session expires the cookie, and drops the server-side entry
+        self.session.clear();
+        self.session_changed = true;
+    }
+
+    fn context(&mut self) -> RspRequestContext {
+        self.context.clone()
     }
 
     fn get_state<T: 'static>(&self) -> Option<&T> {
@@ -100,7 +338,7 @@ impl HttpRequest for AxumRequestAdapter {
 #[cfg(feature = "axum")]
 /// Axum response builder
 pub struct AxumResponseBuilder {
-    content: String,
+    content: Vec<u8>,
     status_code: u16,
     headers: Vec<(String, String)>,
 }
@@ -109,7 +347,7 @@ pub struct AxumResponseBuilder {
 impl HttpResponse for AxumResponseBuilder {
     fn html(content: String) -> Self {
         AxumResponseBuilder {
-            content,
+            content: content.into_bytes(),
             status_code: 200,
             headers: vec![
                 ("Content-Type".to_string(), "text/html; charset=utf-8".to_string()),
@@ -117,9 +355,17 @@ impl HttpResponse for AxumResponseBuilder {
         }
     }
 
+    fn bytes(content_type: &str, body: Vec<u8>) -> Self {
+        AxumResponseBuilder {
+            content: body,
+            status_code: 200,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+        }
+    }
+
     fn redirect(location: &str) -> Self {
         AxumResponseBuilder {
-            content: location.to_string(),
+            content: location.as_bytes().to_vec(),
             status_code: 302,
             headers: vec![
                 ("Location".to_string(), location.to_string()),
@@ -129,7 +375,7 @@ impl HttpResponse for AxumResponseBuilder {
 
     fn error(status_code: u16, message: String) -> Self {
         AxumResponseBuilder {
-            content: message,
+            content: message.into_bytes(),
             status_code,
             headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
         }
@@ -138,23 +384,37 @@ impl HttpResponse for AxumResponseBuilder {
     fn set_header(&mut self, name: &str, value: &str) {
         self.headers.push((name.to_string(), value.to_string()));
     }
+
+    fn set_status(&mut self, status_code: u16) {
+        self.status_code = status_code;
+    }
 }
 
 #[cfg(feature = "axum")]
 impl AxumResponseBuilder {
     pub fn into_axum_response(self) -> impl IntoResponse {
-        let mut response = axum::response::Html(self.content).into_response();
+        let mut response = Response::new(Body::from(self.content));
 
         // Set status code
         let status = StatusCode::from_u16(self.status_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
         *response.status_mut() = status;
 
-        // Set headers
+        // Set headers, skipping the ones that are not valid HTTP
         for (name, value) in self.headers {
-            response.headers_mut().insert(
-                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
-                header::HeaderValue::from_str(&value).unwrap(),
-            );
+            match (
+                header::HeaderName::from_bytes(name.as_bytes()),
+                header::HeaderValue::from_str(&value),
+            ) {
+                (Ok(name), Ok(value)) if name == header::SET_COOKIE => {
+                    response.headers_mut().append(name, value);
+                }
+                (Ok(name), Ok(value)) => {
+                    response.headers_mut().insert(name, value);
+                }
+                _ => {
+                    warn!("Skipping invalid response header {}: {}", name, value);
+                }
+            }
         }
 
         response
@@ -166,242 +426,101 @@ impl AxumResponseBuilder {
 /// Uses spawn_blocking to run all page processing synchronously, avoiding Send/Sync issues
 pub async fn axum_handler_fn<S, T, TA>(
     args: (
-        axum::extract::Query<HashMap<String, String>>,
-        Option<axum::extract::Form<HashMap<String, String>>>,
-        axum::extract::State<Arc<tokio::sync::Mutex<SessionData>>>,
+        axum::extract::Query<Vec<(String, String)>>,
+        Option<axum::extract::Form<Vec<(String, String)>>>,
+        axum::extract::State<SessionStore>,
+        axum::http::request::Parts,
     ),
 ) -> axum::http::Response<axum::body::Body>
 where
     S: RspState<T, TA> + 'static,
     T: RspKey + 'static,
-    TA: RspUserAuth + serde::Serialize + serde::de::DeserializeOwned + Default + 'static,
+    TA: RspUserAuth + RspSessionValue,
 {
-    let (query, form, session_state) = args;
-    let session = session_state.0.clone();
+    let (query, form, store, parts) = args;
 
     // Run all page processing in a blocking task (sync code, no Send/Sync issues)
     let result = tokio::task::spawn_blocking(move || {
-        // Create adapter for request processing
-        let mut adapter = AxumRequestAdapter::new(query, form, session.clone());
-
-        // Load authenticated user from session (blocking lock)
-        let session_auth: Option<TA> = {
-            let session_data = session.blocking_lock();
-            if let Some(ref auth_json) = session_data.auth_json {
-                serde_json::from_str(auth_json).ok()
-            } else {
-                None
-            }
-        };
-
-        let auth = if let Some(auth_from_session) = session_auth {
-            auth_from_session
-        } else {
-            let auth_res = TA::from_request(&mut adapter);
-            match auth_res {
-                Ok(a) => a,
-                Err(login_url) => {
-                    let resp = AxumResponseBuilder::redirect(&login_url);
-                    return resp.into_axum_response().into_response();
-                }
-            }
-        };
-
-        // Get form data and query parameters
-        let form_data = adapter.form_data().unwrap_or_default();
-        let query_params = adapter.query_params().unwrap_or_default();
-
-        // Extract event
-        let event = extract_event(&form_data);
-
-        // Get or reconstruct state
-        let mut maybe_state_val: Option<serde_json::Value> =
-            extract_json_state(&form_data, "state_json");
-
-        let maybe_state = if let Some(ref mut state_val) = maybe_state_val {
-            amend_json_value(state_val, &form_data);
-            serde_json::from_value(state_val.clone()).ok()
-        } else {
-            None
-        };
-
-        let maybe_initial_state: Option<S> =
-            extract_json_state(&form_data, "initial_state_json");
+        let mut adapter = AxumRequestAdapter::new(parts, query, form, store.0);
 
-        // Get key
-        let mut maybe_key = S::get_key(&auth, &query_params, &maybe_state);
-        if maybe_key.is_none() {
-            maybe_key = S::get_key_from_args(&auth, &query_params);
-        }
-        let mut key = maybe_key.unwrap_or_default();
-
-        // Get current initial state
-        let mut curr_initial_state = S::get_state(&auth, key.clone());
-        let state_none = maybe_state.is_none();
-        let initial_state_none = maybe_initial_state.is_none();
-        let initial_state = maybe_initial_state.unwrap_or(curr_initial_state.clone());
-        let state = maybe_state.unwrap_or(initial_state.clone());
-
-        // Handle event
-        let ri = crate::core::RspInfo {
-            auth: &auth,
-            event: &event,
-            key: &key,
-            state,
-            state_none,
-            initial_state,
-            initial_state_none,
-            curr_initial_state: &curr_initial_state,
-        };
+        let outcome = PageEngine::<S, T, TA>::run(&mut adapter);
 
-        let r = S::event_handler(ri);
-        let mut initial_state = r.initial_state;
-        let mut state = r.state;
-        let action = r.action;
-        let new_auth = r.new_auth;
-
-        // Save auth to session (blocking lock)
-        if let Some(new_auth_any) = new_auth {
-            let auth_json_opt = if let Some(new_auth_typed) = new_auth_any.downcast_ref::<TA>() {
-                serde_json::to_string(new_auth_typed).ok()
-            } else if let Some(new_auth_cookie) = new_auth_any.downcast_ref::<crate::CookiePageAuth>() {
-                serde_json::to_string(new_auth_cookie).ok()
-            } else {
-                None
-            };
-
-            if let Some(auth_json) = auth_json_opt {
-                let mut session_data = session.blocking_lock();
-                session_data.auth_json = Some(auth_json);
-            }
+        let mut resp: AxumResponseBuilder = outcome.into_response();
+        if let Some(cookie) = adapter.save_session() {
+            resp.set_header("Set-Cookie", &cookie);
         }
-
-        // Process action
-        let mut redirect_to = String::new();
-        match action {
-            RspAction::Render => {}
-            RspAction::ReloadState => {
-                curr_initial_state = S::get_state(&auth, key.clone());
-                initial_state = curr_initial_state.clone();
-                state = curr_initial_state.clone();
-            }
-            RspAction::RedirectTo(target) => {
-                redirect_to = target;
-            }
-            RspAction::SetKey(k) => {
-                key = k;
-                curr_initial_state = S::get_state(&auth, key.clone());
-                initial_state = curr_initial_state.clone();
-                state = curr_initial_state.clone();
-            }
-        }
-
-        if !redirect_to.is_empty() {
-            let resp = AxumResponseBuilder::redirect(&redirect_to);
-            return resp.into_axum_response().into_response();
-        }
-
-        // Render template
-        let template_name = if S::get_template_name() != "" {
-            S::get_template_name()
-        } else {
-            S::get_template_name_auto()
-        };
-
-        let template = match maybe_compile_template(&template_name) {
-            Ok(t) => t,
-            Err(e) => {
-                let resp = AxumResponseBuilder::error(500, format!("Template error: {}", e));
-                return resp.into_axum_response().into_response();
-            }
-        };
-
-        // Fill data
-        let ri = crate::core::RspInfo {
-            auth: &auth,
-            event: &event,
-            key: &key,
-            state,
-            state_none: false,
-            initial_state,
-            initial_state_none: false,
-            curr_initial_state: &curr_initial_state,
-        };
-
-        let r = S::fill_data(ri);
-        let initial_state = r.initial_state;
-        let state = r.state;
-        let data = r.data;
-
-        // Build template data
-        let data = data.insert("auth", &auth).unwrap();
-        let data = data.insert("state", &state).unwrap();
-        let data = data.insert("state_key", &key).unwrap();
-        let data = data.insert("initial_state", &initial_state).unwrap();
-        let data = data.insert("curr_initial_state", &curr_initial_state).unwrap();
-        let data = data
-            .insert("state_json", &serde_json::to_string(&state).unwrap())
-            .unwrap();
-        let data = data
-            .insert("state_key_json", &serde_json::to_string(&key).unwrap())
-            .unwrap();
-        let data = data
-            .insert("initial_state_json", &serde_json::to_string(&initial_state).unwrap())
-            .unwrap();
-        let data = data
-            .insert("curr_initial_state_json", &serde_json::to_string(&curr_initial_state).unwrap())
-            .unwrap();
-
-        // Render
-        let mut bytes = vec![];
-        let data_built = data.build();
-        template
-            .render_data(&mut bytes, &data_built)
-            .expect("Failed to render");
-        let payload = std::str::from_utf8(&bytes).unwrap();
-
-        let resp = AxumResponseBuilder::html(payload.to_string());
         resp.into_axum_response().into_response()
     })
-    .await
-    .unwrap(); // Unwrap the JoinHandle
-
-    result
+    .await;
+
+    match result {
+        Ok(response) => response,
+        Err(e) => {
+            // The page panicked - do not take the worker down with it, and keep the details in the log
+            error!("{} failed: {}", std::any::type_name::<S>(), e);
+            let err = HttpError::InternalError(String::from("page task failed"));
+            let resp: AxumResponseBuilder = PageEngine::<S, T, TA>::error_outcome(err).into_response();
+            resp.into_axum_response().into_response()
+        }
+    }
 }
 
 #[cfg(feature = "axum")]
 /// Generic Axum handler factory that returns a proper Handler implementation
 pub fn make_axum_handler<S, T, TA>() -> impl Fn(
-    axum::extract::Query<HashMap<String, String>>,
-    Option<axum::extract::Form<HashMap<String, String>>>,
-    axum::extract::State<Arc<tokio::sync::Mutex<SessionData>>>,
-) -> std::pin::Pin<Box<dyn std::future::Future<Output = axum::http::Response<axum::body::Body>> + Send>>
+    axum::extract::State<SessionStore>,
+    axum::http::request::Parts,
+    axum::extract::Query<Vec<(String, String)>>,
+    Option<axum::extract::Form<Vec<(String, String)>>>,
+) -> std::pin::Pin<Box<dyn std::future::Future<Output = axum::http::Response<axum::body::Body>> + Send>> + Clone
 where
     S: RspState<T, TA> + 'static,
     T: RspKey + 'static,
-    TA: RspUserAuth + serde::Serialize + serde::de::DeserializeOwned + Default + 'static,
+    TA: RspUserAuth + RspSessionValue,
 {
-    move |query, form, session_state| {
-        Box::pin(axum_handler_fn::<S, T, TA>((query, form, session_state)))
+    move |store, parts, query, form| {
+        Box::pin(axum_handler_fn::<S, T, TA>((query, form, store, parts)))
     }
 }
 
 #[cfg(feature = "axum")]
 /// Axum server wrapper similar to RspServer for Iron
 pub struct RspAxumServer {
-    session_data: Arc<tokio::sync::Mutex<SessionData>>,
+    session_store: SessionStore,
 }
 
 #[cfg(feature = "axum")]
 impl RspAxumServer {
+    /// Server keeping the sessions in encrypted cookies
     pub fn new() -> Self {
         Self {
-            session_data: Arc::new(tokio::sync::Mutex::new(SessionData::default())),
+            session_store: SessionStore::encrypted_cookie(),
+        }
+    }
+
+    /// Server keeping the sessions in memory, keyed by a session ID cookie
+    pub fn with_server_sessions() -> Self {
+        Self {
+            session_store: SessionStore::server(),
         }
     }
 
-    pub fn session_data(&self) -> Arc<tokio::sync::Mutex<SessionData>> {
-        self.session_data.clone()
+    /// Send the session cookie only over HTTPS - for a server behind a TLS proxy
+    pub fn set_session_cookie_secure(&mut self, secure: bool) {
+        self.session_store = self.session_store.clone().secure(secure);
+    }
+
+    /// SameSite of the session cookie: "Strict", "Lax" (the default) or "None"
+    pub fn set_session_cookie_same_site(&mut self, same_site: &str) {
+        self.session_store = self.session_store.clone().same_site(same_site);
+    }
+
+    pub fn set_secret(&mut self, new_secret: Vec<u8>) {
+        crate::state_codec::set_state_secret(&new_secret);
+    }
+
+    pub fn session_store(&self) -> SessionStore {
+        self.session_store.clone()
     }
 
     pub async fn run(
@@ -414,8 +533,17 @@ impl RspAxumServer {
 
         let addr = SocketAddr::from(([127, 0, 0, 1], port));
         println!("HTTP server for {} (Axum) starting on {}", title, addr);
+        crate::template_cache::preload_templates_at_boot();
 
         let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-        axum::serve(listener, router).await.unwrap();
+        // Keep the client address, for RspRequestContext::remote_addr
+        let service = router.into_make_service_with_connect_info::<SocketAddr>();
+        let stop = async {
+            while !crate::server_stop_requested() {
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            }
+        };
+        axum::serve(listener, service).with_graceful_shutdown(stop).await.unwrap();
+        println!("HTTP server for {} (Axum) stopped", title);
     }
-}
\ No newline at end of file
+}
@@ -2,7 +2,7 @@
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 use chrono::NaiveDateTime;
-use crate::{RspUserAuth, HttpRequest};
+use crate::{RspUserAuth, HttpRequest, RspSessionValue};
 
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct NoPageAuth {}
@@ -13,6 +13,12 @@ impl RspUserAuth for NoPageAuth {
     }
 }
 
+impl RspSessionValue for NoPageAuth {
+    fn session_key() -> &'static str {
+        "rsp10_no_auth"
+    }
+}
+
 #[cfg(feature = "iron")]
 impl iron::typemap::Key for NoPageAuth {
     type Value = NoPageAuth;
@@ -57,12 +63,18 @@ impl CookiePageAuth {
 
 impl RspUserAuth for CookiePageAuth {
     fn from_request<Req: HttpRequest>(_req: &mut Req) -> Result<CookiePageAuth, String> {
-        // Session checking is handled by the Iron handler
-        // If there's no session, redirect to login
+        // The session is checked by the page engine before this is called,
+        // so if we get here there is no session - redirect to login
         Err("/login".to_string())
     }
 }
 
+impl RspSessionValue for CookiePageAuth {
+    fn session_key() -> &'static str {
+        "rsp10_session_cookie"
+    }
+}
+
 #[cfg(feature = "iron")]
 impl iron::typemap::Key for CookiePageAuth {
     type Value = CookiePageAuth;
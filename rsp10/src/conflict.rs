@@ -0,0 +1,120 @@
+/// Detection of changes made in the background while a page was open
+///
+/// The *Initial State* comes back from the browser as it was when the page
+/// was rendered, while the *Current Initial State* is freshly computed
+/// from the key. Any top-level field where the two differ has been changed
+/// by someone else in the meantime.
+
+use serde_json::{Map, Value};
+
+/// A state field that was changed in the background since the page was rendered
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RspFieldConflict {
+    pub field: String,
+    /// Value when the page was rendered
+    pub initial: Value,
+    /// Value as it is now
+    pub current: Value,
+    /// Value submitted by the user
+    pub submitted: Value,
+    /// Whether the user has changed the field too
+    pub user_modified: bool,
+}
+
+/// What the framework does when it detects conflicts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RspConflictPolicy {
+    /// Only report the conflicts in RspInfo, the event handler decides
+    Report,
+    /// Take over the background changes to the fields the user has not
+    /// touched, and report the remaining conflicts
+    AutoMerge,
+    /// Like AutoMerge, but if any conflicts remain the event is not
+    /// handled and the page is rendered again with the conflicts marked,
+    /// relative to the current data
+    Refuse,
+}
+
+fn state_fields<S: serde::Serialize>(state: &S) -> Map<String, Value> {
+    match serde_json::to_value(state) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Compare the three states and return the fields changed in the background
+pub fn find_conflicts<S: serde::Serialize>(
+    initial_state: &S,
+    curr_initial_state: &S,
+    state: &S,
+) -> Vec<RspFieldConflict> {
+    let initial = state_fields(initial_state);
+    let current = state_fields(curr_initial_state);
+    let submitted = state_fields(state);
+
+    let mut conflicts = vec![];
+    for (field, current_val) in current {
+        let initial_val = initial.get(&field).cloned().unwrap_or(Value::Null);
+        if initial_val == current_val {
+            continue;
+        }
+        let submitted_val = submitted.get(&field).cloned().unwrap_or(Value::Null);
+        conflicts.push(RspFieldConflict {
+            user_modified: submitted_val != initial_val,
+            field,
+            initial: initial_val,
+            current: current_val,
+            submitted: submitted_val,
+        });
+    }
+    conflicts
+}
+
+/// Set the given fields of the state to their current values
+pub fn take_over_current<'a, S, I>(state: S, conflicts: I) -> S
+where
+    S: serde::Serialize + serde::de::DeserializeOwned,
+    I: IntoIterator<Item = &'a RspFieldConflict>,
+{
+    let mut fields = state_fields(&state);
+    for c in conflicts {
+        fields.insert(c.field.clone(), c.current.clone());
+    }
+    serde_json::from_value(Value::Object(fields)).unwrap_or(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn change_on_both_sides_is_a_user_conflict() {
+        let conflicts = find_conflicts(&json!({"a": 1, "b": 1}), &json!({"a": 2, "b": 1}), &json!({"a": 3, "b": 1}));
+        assert_eq!(
+            conflicts,
+            vec![RspFieldConflict {
+                field: "a".into(),
+                initial: json!(1),
+                current: json!(2),
+                submitted: json!(3),
+                user_modified: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn background_change_alone_is_taken_over() {
+        let state = json!({"a": 1, "b": 5});
+        let conflicts = find_conflicts(&json!({"a": 1, "b": 1}), &json!({"a": 2, "b": 1}), &state);
+        assert_eq!(conflicts.len(), 1);
+        assert!(!conflicts[0].user_modified);
+        assert_eq!(take_over_current(state, &conflicts), json!({"a": 2, "b": 5}));
+    }
+
+    #[test]
+    fn no_background_change_is_no_conflict() {
+        let initial = json!({"a": 1, "b": 1});
+        assert!(find_conflicts(&initial, &initial, &json!({"a": 3, "b": 4})).is_empty());
+    }
+}
@@ -9,7 +9,14 @@ use std::fmt::Debug;
 use serde;
 use serde_json;
 
-use crate::http_adapter::{HttpRequest, HttpResponse, HttpResult, HttpError};
+use crate::http_adapter::{
+    HttpRequest, HttpResponse, HttpResult, HttpError, RspSessionValue, RspRequestContext, RspResponseHeaders,
+};
+use crate::state_codec::{self, RspStateEncoding};
+use crate::conflict::{find_conflicts, take_over_current, RspConflictPolicy, RspFieldConflict};
+use crate::prg::{self, PrgStash};
+use crate::validation::RspFieldError;
+use crate::engine::RspTemplateData;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RspEvent {
@@ -23,6 +30,18 @@ pub enum RspAction<T> {
     SetKey(T),
     ReloadState,
     RedirectTo(String),
+    /// Redirect with 303 See Other, e.g. after a POST
+    RedirectSeeOther(String),
+    /// Clear the client's session - logging the user out - and redirect to the URL
+    Logout(String),
+    /// Render the page as usual, but with the given HTTP status
+    RenderStatus(u16),
+    /// Send the value as JSON instead of rendering the page
+    Json(serde_json::Value),
+    /// Send the raw body with the content type instead of rendering the page
+    Bytes { content_type: String, body: Vec<u8> },
+    /// Send the body as a file download
+    Download { filename: String, content_type: String, body: Vec<u8> },
 }
 
 /// Extract event from form data
@@ -63,87 +82,283 @@ pub fn extract_event(form_data: &HashMap<String, Vec<String>>) -> RspEvent {
     RspEvent { event, target }
 }
 
-/// Extract JSON state from form field
-pub fn extract_json_state<S>(form_data: &HashMap<String, Vec<String>>, field_name: &str) -> Option<S>
+/// Extract JSON state from form field, rejecting it if the signature for the binding
+/// does not match, see `state_codec::state_binding`
+pub fn extract_json_state<S>(form_data: &HashMap<String, Vec<String>>, field_name: &str, binding: &str) -> Option<S>
 where
     S: serde::de::DeserializeOwned,
 {
     form_data
         .get(field_name)
         .and_then(|v| v.first())
-        .and_then(|json_str| serde_json::from_str(json_str).ok())
+        .and_then(|encoded| state_codec::decode_state(binding, encoded))
+        .and_then(|json_str| serde_json::from_str(&json_str).ok())
 }
 
-/// Amend JSON value with form data
+/// Serialize and encode the state for the hidden form field with the given binding
+pub fn encode_json_state<S>(state: &S, binding: &str, encoding: RspStateEncoding) -> String
+where
+    S: serde::Serialize,
+{
+    state_codec::encode_state(binding, &serde_json::to_string(state).unwrap(), encoding)
+}
+
+/// Whether the form input of the name may amend the page state `S`: only the fields
+/// with an input element, and those of the rows of the grids, see `RspStateMeta::input_fields`
+pub fn is_state_input<S: RspStateMeta>(name: &str) -> bool {
+    let parts: Vec<&str> = name.split("__").collect();
+    match parts[..] {
+        [field] => S::input_fields().contains(&field),
+        [grid, index, field] if index.parse::<usize>().is_ok() => S::grid_input_fields(grid).contains(&field),
+        _ => false,
+    }
+}
+
+/// Amend JSON value with the form data of the inputs accepted by `is_input`
 pub fn amend_json_value(
     orig_val: &mut serde_json::Value,
     form_data: &HashMap<String, Vec<String>>,
+    is_input: &dyn Fn(&str) -> bool,
 ) {
-    amend_value_recursive("", orig_val, form_data);
+    let mut amendments = vec![];
+    collect_amendments("", "", orig_val, form_data, is_input, &mut amendments);
+    for a in amendments {
+        if let (Some(val), Some(first)) = (orig_val.pointer_mut(&a.pointer), a.candidates.into_iter().next()) {
+            *val = first;
+        }
+    }
 }
 
-fn amend_value_recursive(
+/// Amend the state with form data, and deserialize it.
+///
+/// Only the inputs of the state are taken, see `is_state_input` - anything else
+/// posted under the name of a field would override the verified state.
+///
+/// The JSON value alone does not tell the type of the field - an empty number
+/// input may mean None for an Option<i32>, "123" may be meant for an Option<String>.
+/// If the first guess for each input does not deserialize, the inputs are settled
+/// one by one: each gets the first of its candidate values that the state type
+/// accepts, or keeps the value it had.
+pub fn amend_json_state<S: serde::de::DeserializeOwned + RspStateMeta>(
+    state_val: serde_json::Value,
+    form_data: &HashMap<String, Vec<String>>,
+) -> Option<S> {
+    let mut amendments = vec![];
+    collect_amendments("", "", &state_val, form_data, &is_state_input::<S>, &mut amendments);
+
+    let mut amended = state_val.clone();
+    for a in &amendments {
+        if let (Some(val), Some(first)) = (amended.pointer_mut(&a.pointer), a.candidates.first()) {
+            *val = first.clone();
+        }
+    }
+    if let Ok(state) = serde_json::from_value(amended) {
+        return Some(state);
+    }
+
+    let mut settled = state_val;
+    for a in amendments {
+        for candidate in a.candidates {
+            let mut attempt = settled.clone();
+            if let Some(val) = attempt.pointer_mut(&a.pointer) {
+                *val = candidate;
+            }
+            if serde_json::from_value::<S>(attempt.clone()).is_ok() {
+                settled = attempt;
+                break;
+            }
+        }
+    }
+    serde_json::from_value(settled).ok()
+}
+
+/// The values a form input may stand for, best guess first
+struct RspAmendment {
+    pointer: String,
+    candidates: Vec<serde_json::Value>,
+}
+
+fn collect_amendments(
     name_prefix: &str,
-    orig_val: &mut serde_json::Value,
+    pointer: &str,
+    orig_val: &serde_json::Value,
     form_data: &HashMap<String, Vec<String>>,
+    is_input: &dyn Fn(&str) -> bool,
+    amendments: &mut Vec<RspAmendment>,
 ) {
     use serde_json::Value::*;
     match orig_val {
-        Object(ref mut obj) => {
-            for (key, mut value) in obj.iter_mut() {
+        Object(ref obj) => {
+            for (key, value) in obj.iter() {
                 let new_prefix = if name_prefix == "" {
                     format!("{}", key)
                 } else {
                     format!("{}__{}", name_prefix, key)
                 };
-                amend_value_recursive(&new_prefix, value, form_data);
+                let new_pointer = format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"));
+                collect_amendments(&new_prefix, &new_pointer, value, form_data, is_input, amendments);
             }
         }
-        Array(ref mut arr) => {
-            for (i, elt) in arr.iter_mut().enumerate() {
+        Array(ref arr) => {
+            let candidates = if is_input(name_prefix) {
+                list_candidates(name_prefix, arr, form_data)
+            } else {
+                None
+            };
+            if let Some(candidates) = candidates {
+                amendments.push(RspAmendment {
+                    pointer: pointer.to_string(),
+                    candidates,
+                });
+                return;
+            }
+            for (i, elt) in arr.iter().enumerate() {
                 let new_prefix = format!("{}__{}", name_prefix, i);
-                amend_value_recursive(&new_prefix, elt, form_data);
+                let new_pointer = format!("{}/{}", pointer, i);
+                collect_amendments(&new_prefix, &new_pointer, elt, form_data, is_input, amendments);
             }
         }
+        _ if !is_input(name_prefix) => {}
         ref x => {
-            if form_data.contains_key(name_prefix) {
-                let new_val_src = form_data[name_prefix].clone();
-                let src = &new_val_src[0];
-                match x {
-                    Bool(ref _val) => {
-                        let new_val = match src.as_ref() {
-                            "true" | "on" | "checked" => true,
-                            _ => false,
-                        };
-                        *orig_val = Bool(new_val);
-                    }
-                    String(ref _val) => {
-                        *orig_val = String(src.to_string());
-                    }
-                    _ => {
-                        if let Ok(parsed) = serde_json::from_str(src) {
-                            *orig_val = parsed;
-                        }
-                    }
-                }
+            let candidates = if let Some(src) = form_data.get(name_prefix).and_then(|v| v.first()) {
+                input_candidates(x, src)
             } else {
                 // Workaround for unchecked checkboxes
                 let sentinel_key = format!("{}_sentinel", name_prefix);
-                if form_data.contains_key(&sentinel_key) {
-                    let new_val_src = form_data[&sentinel_key].clone();
-                    let src = &new_val_src[0];
-                    if let Bool(_) = x {
-                        let new_val = matches!(src.as_ref(), "true" | "on" | "checked");
-                        *orig_val = Bool(new_val);
-                    }
+                match (x, form_data.get(&sentinel_key).and_then(|v| v.first())) {
+                    (Bool(_), Some(src)) => vec![Bool(matches!(src.as_ref(), "true" | "on" | "checked"))],
+                    _ => vec![],
+                }
+            };
+            if !candidates.is_empty() {
+                amendments.push(RspAmendment {
+                    pointer: pointer.to_string(),
+                    candidates,
+                });
+            }
+        }
+    }
+}
+
+/// A Vec field bound to a multi-select takes all the values submitted under its name.
+/// Nothing is submitted when nothing is selected, so the sentinel tells the list was on the form.
+fn list_candidates(
+    name_prefix: &str,
+    orig_arr: &[serde_json::Value],
+    form_data: &HashMap<String, Vec<String>>,
+) -> Option<Vec<serde_json::Value>> {
+    use serde_json::Value::*;
+    let values = match form_data.get(name_prefix) {
+        Some(values) => values.clone(),
+        None if form_data.contains_key(&format!("{}_sentinel", name_prefix)) => vec![],
+        None => return None,
+    };
+    let strings = Array(values.iter().map(|v| String(v.clone())).collect());
+    let numbers: Option<Vec<serde_json::Value>> = values
+        .iter()
+        .map(|v| number_candidates(v.trim(), true).into_iter().next())
+        .collect();
+    let mut candidates = vec![];
+    match (orig_arr.first(), numbers) {
+        (Some(String(_)), Some(numbers)) => {
+            candidates.push(strings);
+            candidates.push(Array(numbers));
+        }
+        (_, Some(numbers)) => {
+            candidates.push(Array(numbers));
+            candidates.push(strings);
+        }
+        (_, None) => candidates.push(strings),
+    }
+    Some(candidates)
+}
+
+/// What the text of an input may be, given the value the field had
+fn input_candidates(orig_val: &serde_json::Value, src: &str) -> Vec<serde_json::Value> {
+    use serde_json::Value::*;
+    match orig_val {
+        Bool(_) => vec![Bool(matches!(src, "true" | "on" | "checked"))],
+        String(ref orig) => {
+            if src.is_empty() {
+                // An emptied Option<String> becomes None
+                vec![String(src.to_string()), Null]
+            } else if let Some(with_seconds) = datetime_with_seconds(src) {
+                // Inputs of type datetime-local may omit the seconds, chrono wants them
+                if looks_like_datetime(orig) {
+                    vec![String(with_seconds), String(src.to_string())]
+                } else {
+                    vec![String(src.to_string()), String(with_seconds)]
                 }
+            } else {
+                vec![String(src.to_string())]
             }
         }
+        Number(ref orig) => {
+            let src = src.trim();
+            if src.is_empty() {
+                vec![Null]
+            } else {
+                number_candidates(src, !orig.is_f64())
+            }
+        }
+        Null => {
+            if src.is_empty() {
+                return vec![];
+            }
+            let mut candidates = number_candidates(src.trim(), true);
+            if let Ok(b @ Bool(_)) = serde_json::from_str(src) {
+                candidates.push(b);
+            }
+            candidates.push(String(src.to_string()));
+            if let Some(with_seconds) = datetime_with_seconds(src) {
+                candidates.push(String(with_seconds));
+            }
+            candidates
+        }
+        _ => vec![],
+    }
+}
+
+/// "5.0" from a number input is fine for an integer field too
+fn number_candidates(src: &str, integer_first: bool) -> Vec<serde_json::Value> {
+    let n = match serde_json::from_str::<serde_json::Value>(src) {
+        Ok(serde_json::Value::Number(n)) => n,
+        _ => return vec![],
+    };
+    let mut candidates = vec![];
+    if integer_first && n.is_f64() {
+        let f = n.as_f64().unwrap_or(0.5);
+        if f.fract() == 0.0 && f.abs() < 9.0e15 {
+            candidates.push(serde_json::Value::from(f as i64));
+        }
+    }
+    candidates.push(serde_json::Value::Number(n));
+    candidates
+}
+
+fn looks_like_datetime(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() >= 19 && b[4] == b'-' && b[7] == b'-' && b[10] == b'T' && b[13] == b':' && b[16] == b':'
+}
+
+/// "2024-05-01T10:30" becomes "2024-05-01T10:30:00"
+fn datetime_with_seconds(s: &str) -> Option<String> {
+    let b = s.as_bytes();
+    let digits = [0, 1, 2, 3, 5, 6, 8, 9, 11, 12, 14, 15];
+    if b.len() == 16
+        && b[4] == b'-' && b[7] == b'-' && b[10] == b'T' && b[13] == b':'
+        && digits.iter().all(|&i| b[i].is_ascii_digit())
+    {
+        Some(format!("{}:00", s))
+    } else {
+        None
     }
 }
 
 /// Core state management info passed to handlers
 pub struct RspInfo<'a, R, T, TA> {
+    /// The request being handled
+    pub ctx: &'a RspRequestContext,
     pub auth: &'a TA,
     pub event: &'a RspEvent,
     pub key: &'a T,
@@ -152,19 +367,67 @@ pub struct RspInfo<'a, R, T, TA> {
     pub initial_state: R,
     pub initial_state_none: bool,
     pub curr_initial_state: &'a R,
+    /// Fields changed in the background since the page was rendered
+    pub conflicts: &'a [RspFieldConflict],
+    /// Validation errors of the submitted state
+    pub errors: &'a [RspFieldError],
+}
+
+impl<'a, R, T, TA> RspInfo<'a, R, T, TA> {
+    /// Whether the given state field was changed in the background
+    pub fn has_conflict(&self, field: &str) -> bool {
+        self.conflicts.iter().any(|c| c.field == field)
+    }
+
+    /// The id of the input of the given state field
+    pub fn element_id(&self, field: &str) -> String {
+        field.to_string()
+    }
+
+    /// Whether the submitted state passed the validation
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Validation error message of the given state field
+    pub fn field_error(&self, field: &str) -> Option<&str> {
+        self.errors
+            .iter()
+            .find(|e| e.field == field)
+            .map(|e| e.message.as_str())
+    }
 }
 
 pub struct RspEventHandlerResult<R, T> {
     pub state: R,
     pub initial_state: R,
     pub action: RspAction<T>,
-    pub new_auth: Option<Box<dyn std::any::Any>>,  // Optional new auth to store in session
+    pub new_auth: Option<RspNewAuth>,  // Optional new auth to store in session
+}
+
+/// A new auth object for the session, established by an event handler
+///
+/// The auth type does not need to be the one of the page - e.g. a login
+/// page running with `NoPageAuth` can log the user in with `CookiePageAuth`
+/// or any other auth type that can be kept in the session.
+pub struct RspNewAuth {
+    session_key: &'static str,
+    raw: String,
+}
+
+impl RspNewAuth {
+    pub fn new<A: RspUserAuth + RspSessionValue>(auth: A) -> RspNewAuth {
+        RspNewAuth {
+            session_key: A::session_key(),
+            raw: serde_json::to_string(&auth).unwrap(),
+        }
+    }
 }
 
 pub struct RspFillDataResult<R> {
     pub state: R,
     pub initial_state: R,
-    pub data: mustache::MapBuilder,
+    pub data: RspTemplateData,
 }
 
 /// User authentication trait
@@ -190,8 +453,64 @@ impl RspKey for () {
     }
 }
 
+/// Metadata of the page state, implemented by `#[derive(RspState)]`.
+/// A hand-written impl has to list the input fields of the state in `input_fields`
+/// (and of the grid rows in `grid_input_fields`), or nothing posted amends the state.
+pub trait RspStateMeta {
+    /// Check the `#[rsp_validate(...)]` rules of the fields
+    fn validate(&self) -> Vec<RspFieldError> {
+        vec![]
+    }
+
+    /// Add or remove the rows of the grids, see `grid::grid_event`
+    fn grid_event(&mut self, _event: &RspEvent) -> bool {
+        false
+    }
+
+    /// The template set with `#[rsp_template("...")]`
+    fn template_name() -> Option<&'static str> {
+        None
+    }
+
+    /// The layout set with `#[rsp_layout("...")]`
+    fn layout_name() -> Option<&'static str> {
+        None
+    }
+
+    /// The slot set with `#[rsp_layout("...", slot = "...")]`
+    fn layout_slot() -> Option<&'static str> {
+        None
+    }
+
+    /// The content type set with `#[rsp_content_type("...")]`
+    fn content_type() -> Option<&'static str> {
+        None
+    }
+
+    /// The fields of the state, for `state.xxx` in the template
+    fn template_fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// The elements the derived `fill_data` adds to the data
+    fn template_elements() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// The fields with an input element - the only ones the posted form may change.
+    /// Without the derive, nothing submitted amends the state unless listed here.
+    fn input_fields() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// The input fields of the rows of the grid, see `RspRow::input_fields`
+    fn grid_input_fields(_grid: &str) -> &'static [&'static str] {
+        &[]
+    }
+}
+
 /// Core state trait - framework agnostic
-pub trait RspState<T, TA>
+pub trait RspState<T, TA>: RspStateMeta
 where
     Self: std::marker::Sized + serde::Serialize + serde::de::DeserializeOwned + Clone + Debug,
     TA: RspUserAuth + serde::Serialize,
@@ -200,9 +519,15 @@ where
     /// Get initial state based on key - no HTTP framework dependency
     fn get_state(auth: &TA, key: T) -> Self;
 
+    /// Fallible variant of `get_state`, e.g. for a key that does not exist,
+    /// which also gets to see the request
+    fn try_get_state(_ctx: &RspRequestContext, auth: &TA, key: T) -> HttpResult<Self> {
+        Ok(Self::get_state(auth, key))
+    }
+
     /// Fill data result helper
     fn fill_data_result<'a>(ri: RspInfo<'a, Self, T, TA>, gd: crate::RspDataBuilder) -> RspFillDataResult<Self> {
-        let data = mustache::MapBuilder::new();
+        let data = RspTemplateData::new();
         let initial_state = ri.initial_state;
         let state = ri.state;
         RspFillDataResult {
@@ -226,6 +551,11 @@ where
         }
     }
 
+    /// Fallible variant of `event_handler`
+    fn try_event_handler<'a>(ri: RspInfo<'a, Self, T, TA>) -> HttpResult<RspEventHandlerResult<Self, T>> {
+        Ok(Self::event_handler(ri))
+    }
+
     /// Get key from query parameters (default: delegates to T::from_query_args)
     fn get_key(
         _auth: &TA,
@@ -256,11 +586,25 @@ where
         <Self>::derive_auto_fill_data_impl(ri)
     }
 
+    /// Fallible variant of `fill_data`
+    fn try_fill_data<'a>(ri: RspInfo<'a, Self, T, TA>) -> HttpResult<RspFillDataResult<Self>> {
+        Ok(Self::fill_data(ri))
+    }
+
+    /// Post/Redirect/Get: after a POST, redirect to a GET which renders the
+    /// result, so that reloading the page does not submit the form again
+    fn post_redirect_get() -> bool {
+        false
+    }
+
+    /// Add headers and cookies to the response, called right before it is sent
+    fn response_finalize<'a>(_ri: &RspInfo<'a, Self, T, TA>, _headers: &mut RspResponseHeaders) {}
+
     /// Auto-generated fill_data implementation (generated by derive macro)
     /// Default implementation - just returns empty data
     fn derive_auto_fill_data_impl<'a>(ri: RspInfo<'a, Self, T, TA>) -> RspFillDataResult<Self> {
         println!("DEBUG: derive_auto_fill_data_impl DEFAULT implementation (no derive macro override)");
-        let data = mustache::MapBuilder::new();
+        let data = RspTemplateData::new();
         let initial_state = ri.initial_state;
         let state = ri.state;
         RspFillDataResult {
@@ -270,20 +614,856 @@ where
         }
     }
 
-    /// Get template name (override if needed)
+    /// What to do when the data changed in the background (see RspConflictPolicy)
+    fn conflict_policy() -> RspConflictPolicy {
+        RspConflictPolicy::Report
+    }
+
+    /// How the state is encoded in the hidden form fields (override to seal it)
+    fn state_encoding() -> RspStateEncoding {
+        RspStateEncoding::Signed
+    }
+
+    /// Template used to render the errors returned by the page
+    ///
+    /// It gets `status`, `kind` and `message` as data. If it can not be
+    /// compiled, a plain text error is sent instead.
+    fn get_error_template_name(_err: &HttpError) -> String {
+        "error".into()
+    }
+
+    /// Get template name (override if needed), by default the one of `#[rsp_template]`
     fn get_template_name() -> String {
-        "".into()
+        Self::template_name().unwrap_or_default().into()
+    }
+
+    /// Layout template the page is rendered into: the one of `#[rsp_layout]`,
+    /// else the one set with `rsp10::set_default_layout` - none if empty
+    fn get_layout_name() -> String {
+        match Self::layout_name() {
+            Some(name) => name.into(),
+            None => crate::layout::default_layout(),
+        }
+    }
+
+    /// The name the rendered page gets in the data of the layout
+    fn get_layout_slot() -> String {
+        Self::layout_slot().unwrap_or("content").into()
+    }
+
+    /// Add the state_json/initial_state_json hidden fields to the post forms that lack them
+    fn inject_state_fields() -> bool {
+        true
+    }
+
+    /// Whether the page renders a template - not the case for a page which only ever redirects
+    fn has_template() -> bool {
+        true
+    }
+
+    /// Content type of the rendered page, HTML if empty
+    fn get_content_type() -> String {
+        Self::content_type().unwrap_or_default().into()
     }
 
-    /// Auto-generate template name from type
+    /// Names the `fill_data` adds to the data on top of the derived elements,
+    /// for the template checker
+    fn get_template_data_names() -> Vec<String> {
+        vec![]
+    }
+
+    /// Auto-generate template name from type: the name of the module of the state
     fn get_template_name_auto() -> String {
-        use std::any::type_name;
-        fn test_type<T: ?Sized>() -> String {
-            let full_type_name = type_name::<T>();
-            let components: Vec<String> =
-                full_type_name.split("::").map(|x| x.to_string()).collect();
+        let full_type_name = std::any::type_name::<Self>();
+        // Generic arguments have paths of their own
+        let path = full_type_name.split('<').next().unwrap_or(full_type_name);
+        let components: Vec<&str> = path.split("::").collect();
+        if components.len() >= 2 {
             components[components.len() - 2].to_string()
+        } else {
+            path.to_string()
+        }
+    }
+}
+
+/// Framework-neutral result of running a page
+#[derive(Debug)]
+pub enum RspPageResponse {
+    Html(String),
+    /// Rendered page sent with another status than 200 OK
+    HtmlStatus(u16, String),
+    /// Body other than HTML, with its content type
+    Bytes(String, Vec<u8>),
+    Redirect(String),
+    RedirectSeeOther(String),
+    /// Error rendered with the page's error template
+    ErrorPage(HttpError, String),
+    /// Error sent as plain text
+    Error(HttpError),
+}
+
+/// What the adapter has to send once the page has run
+///
+/// Any session changes have already been applied to the request via
+/// `HttpRequest::set_session_raw`, the adapter has to persist them
+/// along with the response.
+pub struct RspPageOutcome {
+    pub response: RspPageResponse,
+    /// Headers added by the page
+    pub headers: RspResponseHeaders,
+}
+
+impl RspPageOutcome {
+    fn response(response: RspPageResponse) -> Self {
+        RspPageOutcome {
+            response,
+            headers: RspResponseHeaders::new(),
+        }
+    }
+
+    /// Convert the outcome into a framework-specific response
+    pub fn into_response<R: HttpResponse>(self) -> R {
+        let mut resp: R = match self.response {
+            RspPageResponse::Html(content) => R::html(content),
+            RspPageResponse::HtmlStatus(status, content) => {
+                let mut resp = R::html(content);
+                resp.set_status(status);
+                resp
+            }
+            RspPageResponse::Bytes(content_type, body) => R::bytes(&content_type, body),
+            RspPageResponse::Redirect(location) => R::redirect(&location),
+            RspPageResponse::RedirectSeeOther(location) => {
+                let mut resp = R::redirect(&location);
+                resp.set_status(303);
+                resp
+            }
+            RspPageResponse::ErrorPage(err, content) => {
+                let mut resp = R::html(content);
+                resp.set_status(err.status_code());
+                resp
+            }
+            RspPageResponse::Error(err) => {
+                R::error(err.status_code(), format!("{}: {}", err.kind(), err.public_message()))
+            }
+        };
+        for (name, value) in self.headers.iter() {
+            resp.set_header(name, value);
+        }
+        resp
+    }
+}
+
+/// Runs the full page lifecycle against the HttpRequest abstraction
+///
+/// Both the Iron and the Axum adapters delegate to this, so that
+/// the state handling is identical regardless of the HTTP framework.
+pub struct PageEngine<S, T, TA> {
+    _phantom: std::marker::PhantomData<(S, T, TA)>,
+}
+
+impl<S, T, TA> PageEngine<S, T, TA>
+where
+    S: RspState<T, TA>,
+    T: RspKey,
+    TA: RspUserAuth + RspSessionValue,
+{
+    /// Run the page
+    pub fn run<Req: HttpRequest>(req: &mut Req) -> RspPageOutcome {
+        match Self::run_page(req) {
+            Ok(outcome) => outcome,
+            Err(err) => RspPageOutcome::response(Self::error_page(err)),
+        }
+    }
+
+    fn run_page<Req: HttpRequest>(req: &mut Req) -> HttpResult<RspPageOutcome> {
+        let mut ctx = req.context();
+        // The state is bound to the browser, which gets its key on the first visit
+        let mut browser_key_cookie = None;
+        if ctx.cookie(state_codec::BROWSER_KEY_COOKIE).is_none() {
+            let (browser_key, cookie) = state_codec::new_browser_key();
+            ctx.cookies.insert(state_codec::BROWSER_KEY_COOKIE.to_string(), browser_key);
+            browser_key_cookie = Some(cookie);
+        }
+        let auth = match req.get_session::<TA>() {
+            Some(auth_from_session) => auth_from_session,
+            None => match TA::from_request(req) {
+                Ok(a) => a,
+                Err(login_url) => {
+                    return Ok(RspPageOutcome::response(RspPageResponse::Redirect(login_url)));
+                }
+            },
+        };
+
+        let form_data = req.form_data().unwrap_or_default();
+        let query_params = req.query_params().unwrap_or_default();
+
+        let event = extract_event(&form_data);
+
+        // Reconstruct the state from the form, amended by the input fields
+        let state_binding = state_codec::state_binding::<S>("state_json", &ctx);
+        let initial_state_binding = state_codec::state_binding::<S>("initial_state_json", &ctx);
+        let maybe_state: Option<S> = extract_json_state::<serde_json::Value>(&form_data, "state_json", &state_binding)
+            .and_then(|state_val| amend_json_state(state_val, &form_data));
+        let maybe_initial_state: Option<S> =
+            extract_json_state(&form_data, "initial_state_json", &initial_state_binding);
+
+        let mut headers = RspResponseHeaders::new();
+        if let Some(cookie) = browser_key_cookie {
+            headers.set_cookie(&cookie);
         }
-        test_type::<Self>()
+        let use_prg = S::post_redirect_get();
+
+        // The GET after a Post/Redirect/Get renders what the POST left behind
+        let mut stash: Option<PrgStash<S, T>> = None;
+        if use_prg && ctx.method == "GET" && prg::has_stash(&ctx) {
+            stash = prg::take_stash(&ctx);
+            headers.set_cookie(&prg::removal_cookie(&ctx));
+        }
+
+        let mut key = match stash {
+            Some(ref stash) => stash.key.clone(),
+            None => S::get_key(&auth, &query_params, &maybe_state)
+                .or_else(|| S::get_key_from_args(&auth, &query_params))
+                .unwrap_or_default(),
+        };
+
+        let mut curr_initial_state = S::try_get_state(&ctx, &auth, key.clone())?;
+        let state_none = maybe_state.is_none();
+        let initial_state_none = maybe_initial_state.is_none();
+        let mut initial_state = maybe_initial_state.unwrap_or_else(|| curr_initial_state.clone());
+        let mut state = maybe_state.unwrap_or_else(|| initial_state.clone());
+
+        // Detect the changes made in the background since the page was rendered
+        let policy = S::conflict_policy();
+        let mut conflicts = if state_none || initial_state_none {
+            vec![]
+        } else {
+            find_conflicts(&initial_state, &curr_initial_state, &state)
+        };
+        if policy != RspConflictPolicy::Report && !conflicts.is_empty() {
+            let untouched = conflicts.iter().filter(|c| !c.user_modified);
+            state = take_over_current(state, untouched.clone());
+            initial_state = take_over_current(initial_state, untouched);
+            conflicts.retain(|c| c.user_modified);
+        }
+
+        // Check the submitted state before the event handler sees it
+        let mut submitted = !state_none || stash.is_some();
+        let errors = if state_none { vec![] } else { state.validate() };
+
+        let (mut initial_state, mut state, action, new_auth) =
+            if let Some(stash) = stash {
+                conflicts = stash.conflicts;
+                (stash.initial_state, stash.state, RspAction::Render, None)
+            } else if policy == RspConflictPolicy::Refuse && !conflicts.is_empty() {
+                // Do not act on the stale data, show the user what has changed
+                (curr_initial_state.clone(), state, RspAction::Render, None)
+            } else if !state_none && state.grid_event(&event) {
+                // A row was added or removed, nothing for the event handler to do
+                (initial_state, state, RspAction::Render, None)
+            } else {
+                let ri = RspInfo {
+                    ctx: &ctx,
+                    auth: &auth,
+                    event: &event,
+                    key: &key,
+                    state,
+                    state_none,
+                    initial_state,
+                    initial_state_none,
+                    curr_initial_state: &curr_initial_state,
+                    conflicts: &conflicts,
+                    errors: &errors,
+                };
+                let r = S::try_event_handler(ri)?;
+                (r.initial_state, r.state, r.action, r.new_auth)
+            };
+
+        // Store the new auth before anything is sent, so a redirect carries it
+        if let Some(new_auth) = new_auth {
+            req.set_session_raw(new_auth.session_key, new_auth.raw);
+        }
+
+        let mut render_status = None;
+        let mut response = match action {
+            RspAction::Render => None,
+            RspAction::ReloadState => {
+                curr_initial_state = S::try_get_state(&ctx, &auth, key.clone())?;
+                initial_state = curr_initial_state.clone();
+                state = curr_initial_state.clone();
+                conflicts.clear();
+                submitted = false;
+                None
+            }
+            RspAction::RedirectTo(target) => Some(RspPageResponse::Redirect(target)),
+            RspAction::RedirectSeeOther(target) => Some(RspPageResponse::RedirectSeeOther(target)),
+            RspAction::Logout(target) => {
+                req.clear_session();
+                Some(RspPageResponse::Redirect(target))
+            }
+            RspAction::RenderStatus(status) => {
+                render_status = Some(status);
+                None
+            }
+            RspAction::Json(value) => {
+                let body = serde_json::to_vec(&value).map_err(data_error)?;
+                Some(RspPageResponse::Bytes("application/json".to_string(), body))
+            }
+            RspAction::Bytes { content_type, body } => Some(RspPageResponse::Bytes(content_type, body)),
+            RspAction::Download { filename, content_type, body } => {
+                headers.add("Content-Disposition", &content_disposition(&filename));
+                Some(RspPageResponse::Bytes(content_type, body))
+            }
+            RspAction::SetKey(k) => {
+                key = k;
+                curr_initial_state = S::try_get_state(&ctx, &auth, key.clone())?;
+                initial_state = curr_initial_state.clone();
+                state = curr_initial_state.clone();
+                conflicts.clear();
+                submitted = false;
+                None
+            }
+        };
+
+        if use_prg && ctx.method == "POST" && response.is_none() && render_status.is_none() {
+            let stash = PrgStash {
+                key: key.clone(),
+                state: state.clone(),
+                initial_state: initial_state.clone(),
+                conflicts: conflicts.clone(),
+            };
+            if let Some(cookie) = prg::stash_cookie(&ctx, &stash) {
+                headers.set_cookie(&cookie);
+                response = Some(RspPageResponse::RedirectSeeOther(prg::redirect_url(&ctx)));
+            }
+        }
+
+        // The errors shown are the ones of the state as it is rendered
+        let errors = if submitted { state.validate() } else { vec![] };
+
+        let ri = RspInfo {
+            ctx: &ctx,
+            auth: &auth,
+            event: &event,
+            key: &key,
+            state,
+            state_none: false,
+            initial_state,
+            initial_state_none: false,
+            curr_initial_state: &curr_initial_state,
+            conflicts: &conflicts,
+            errors: &errors,
+        };
+
+        if let Some(response) = response {
+            S::response_finalize(&ri, &mut headers);
+            return Ok(RspPageOutcome { response, headers });
+        }
+        let mut outcome = Self::render(ri, headers)?;
+        if let (Some(status), RspPageResponse::Html(content)) = (render_status, &mut outcome.response) {
+            outcome.response = RspPageResponse::HtmlStatus(status, std::mem::take(content));
+        }
+        Ok(outcome)
+    }
+
+    /// Fill the template data and render the page template
+    fn render(ri: RspInfo<S, T, TA>, mut headers: RspResponseHeaders) -> HttpResult<RspPageOutcome> {
+        let template_name = if S::get_template_name() != "" {
+            S::get_template_name()
+        } else {
+            S::get_template_name_auto()
+        };
+
+        let engine = crate::engine::template_engine();
+        engine.load(&template_name).map_err(template_error)?;
+
+        let ctx = ri.ctx;
+        let auth = ri.auth;
+        let event = ri.event;
+        let key = ri.key;
+        let curr_initial_state = ri.curr_initial_state;
+        let conflicts = ri.conflicts;
+        let errors = ri.errors;
+
+        let r = S::try_fill_data(ri)?;
+        let mut data = r.data;
+
+        let ri = RspInfo {
+            ctx,
+            auth,
+            event,
+            key,
+            state: r.state,
+            state_none: false,
+            initial_state: r.initial_state,
+            initial_state_none: false,
+            curr_initial_state,
+            conflicts,
+            errors,
+        };
+        // Before response_finalize, so that the page can still override it
+        let content_type = S::get_content_type();
+        if content_type != "" {
+            headers.add("Content-Type", &content_type);
+        }
+        S::response_finalize(&ri, &mut headers);
+        let state = ri.state;
+        let initial_state = ri.initial_state;
+
+        data.insert("auth", auth).map_err(data_error)?;
+        data.insert("state", &state).map_err(data_error)?;
+        data.insert("state_key", key).map_err(data_error)?;
+        data.insert("initial_state", &initial_state).map_err(data_error)?;
+        data.insert("curr_initial_state", curr_initial_state).map_err(data_error)?;
+        data.insert("conflicts", &conflicts).map_err(data_error)?;
+        data.insert("has_conflicts", &!conflicts.is_empty()).map_err(data_error)?;
+        data.insert("errors", &errors).map_err(data_error)?;
+        data.insert("has_errors", &!errors.is_empty()).map_err(data_error)?;
+        let state_binding = state_codec::state_binding::<S>("state_json", ctx);
+        let initial_state_binding = state_codec::state_binding::<S>("initial_state_json", ctx);
+        let state_json = encode_json_state(&state, &state_binding, S::state_encoding());
+        let initial_state_json = encode_json_state(&initial_state, &initial_state_binding, S::state_encoding());
+        data.insert("state_json", &state_json).map_err(data_error)?;
+        data.insert("state_key_json", &serde_json::to_string(key).map_err(data_error)?)
+            .map_err(data_error)?;
+        data.insert("initial_state_json", &initial_state_json).map_err(data_error)?;
+        data.insert("curr_initial_state_json", &serde_json::to_string(curr_initial_state).map_err(data_error)?)
+            .map_err(data_error)?;
+
+        let mut content = engine.render(&template_name, &data).map_err(template_error)?;
+
+        let layout_name = S::get_layout_name();
+        if layout_name != "" {
+            data.insert_html(&S::get_layout_slot(), content);
+            content = engine.render(&layout_name, &data).map_err(template_error)?;
+        }
+        if S::inject_state_fields() {
+            content = crate::layout::inject_hidden_fields(
+                &content,
+                &[("state_json", &state_json), ("initial_state_json", &initial_state_json)],
+            );
+        }
+
+        Ok(RspPageOutcome {
+            response: RspPageResponse::Html(content),
+            headers,
+        })
+    }
+
+    /// The outcome for an error which happened outside of the page lifecycle
+    pub(crate) fn error_outcome(err: HttpError) -> RspPageOutcome {
+        RspPageOutcome::response(Self::error_page(err))
+    }
+
+    /// Render the error with the page's error template, falling back to plain text
+    fn error_page(err: HttpError) -> RspPageResponse {
+        match err {
+            HttpError::InternalError(_) => error!("{}: {}", std::any::type_name::<S>(), err),
+            _ => warn!("{}: {}", std::any::type_name::<S>(), err),
+        }
+        let mut data = RspTemplateData::new();
+        data.insert_value("status", serde_json::Value::String(err.status_code().to_string()));
+        data.insert_value("kind", serde_json::Value::String(err.kind().to_string()));
+        data.insert_value("message", serde_json::Value::String(err.public_message().to_string()));
+        match crate::engine::template_engine().render(&S::get_error_template_name(&err), &data) {
+            Ok(content) => RspPageResponse::ErrorPage(err, content),
+            Err(_) => RspPageResponse::Error(err),
+        }
+    }
+}
+
+/// The Content-Disposition of a download. Header values are ASCII, so a name with
+/// other characters goes as `filename*` (RFC 5987), with an ASCII `filename` for older clients.
+fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c == ' ' || c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .collect();
+    if fallback == filename {
+        return format!("attachment; filename=\"{}\"", fallback);
+    }
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => encoded.push(byte as char),
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
+
+fn data_error<E: std::fmt::Display>(e: E) -> HttpError {
+    HttpError::InternalError(format!("Template data error: {}", e))
+}
+
+fn template_error(e: crate::engine::RspTemplateError) -> HttpError {
+    HttpError::InternalError(format!("Template error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_auth::NoPageAuth;
+    use crate::engine::{RspTemplateEngine, RspTemplateError};
+    use std::cell::{Cell, RefCell};
+
+    thread_local! {
+        /// The steps of the lifecycle, in the order the page went through them
+        static STEPS: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+        /// The state as changed in the background, if it was
+        static BACKGROUND: RefCell<Option<TestState>> = const { RefCell::new(None) };
+        static POLICY: Cell<RspConflictPolicy> = const { Cell::new(RspConflictPolicy::Report) };
+        /// The state and the conflicting fields the event handler got
+        static HANDLED: RefCell<Option<(TestState, Vec<String>)>> = const { RefCell::new(None) };
+    }
+
+    fn step(name: &str) {
+        STEPS.with(|steps| steps.borrow_mut().push(name.to_string()));
+    }
+
+    fn take_steps() -> Vec<String> {
+        STEPS.with(|steps| std::mem::take(&mut *steps.borrow_mut()))
+    }
+
+    /// Renders the signed states one per line, so that they can be posted back
+    struct TestEngine;
+
+    impl RspTemplateEngine for TestEngine {
+        fn extension(&self) -> &'static str {
+            "test"
+        }
+
+        fn load(&self, _name: &str) -> Result<(), RspTemplateError> {
+            Ok(())
+        }
+
+        fn render(&self, name: &str, data: &RspTemplateData) -> Result<String, RspTemplateError> {
+            step(&format!("render {}", name));
+            let value = |key: &str| data.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+            if name == "error" {
+                return Ok(format!("{} {}", value("status"), value("message")));
+            }
+            Ok(format!("{}\n{}", value("state_json"), value("initial_state_json")))
+        }
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    struct TestState {
+        name: String,
+        note: String,
+    }
+
+    impl RspStateMeta for TestState {
+        fn template_name() -> Option<&'static str> {
+            Some("test_page")
+        }
+
+        fn input_fields() -> &'static [&'static str] {
+            &["name"]
+        }
+    }
+
+    impl RspState<(), NoPageAuth> for TestState {
+        fn get_state(_auth: &NoPageAuth, _key: ()) -> Self {
+            step("get_state");
+            BACKGROUND
+                .with(|background| background.borrow().clone())
+                .unwrap_or(TestState { name: "initial".into(), note: "".into() })
+        }
+
+        fn conflict_policy() -> RspConflictPolicy {
+            POLICY.with(|policy| policy.get())
+        }
+
+        fn try_get_state(ctx: &RspRequestContext, auth: &NoPageAuth, key: ()) -> HttpResult<Self> {
+            if ctx.path == "/missing" {
+                return Err(HttpError::NotFound("no such page".into()));
+            }
+            Ok(Self::get_state(auth, key))
+        }
+
+        fn event_handler<'a>(ri: RspInfo<'a, Self, (), NoPageAuth>) -> RspEventHandlerResult<Self, ()> {
+            step(&format!("event_handler {} {}", ri.event.event, ri.state.name));
+            let fields = ri.conflicts.iter().map(|c| c.field.clone()).collect();
+            HANDLED.with(|handled| *handled.borrow_mut() = Some((ri.state.clone(), fields)));
+            let action = if ri.event.target == "_save" {
+                RspAction::RedirectTo("/done".into())
+            } else {
+                RspAction::Render
+            };
+            RspEventHandlerResult {
+                initial_state: ri.initial_state,
+                state: ri.state,
+                action,
+                new_auth: None,
+            }
+        }
+
+        fn fill_data<'a>(ri: RspInfo<'a, Self, (), NoPageAuth>) -> RspFillDataResult<Self> {
+            step(&format!("fill_data {}", ri.state.name));
+            Self::fill_data_result(ri, crate::RspDataBuilder::new())
+        }
+    }
+
+    /// A request held in memory, as an adapter would build it
+    #[derive(Default)]
+    struct TestRequest {
+        ctx: RspRequestContext,
+        form: HashMap<String, Vec<String>>,
+        session: HashMap<String, String>,
+    }
+
+    impl TestRequest {
+        fn new(method: &str, path: &str, fields: &[(&str, &str)]) -> Self {
+            let mut req = TestRequest { form: form(fields), ..Default::default() };
+            req.ctx.method = method.into();
+            req.ctx.path = path.into();
+            req
+        }
+    }
+
+    impl HttpRequest for TestRequest {
+        fn query_params(&mut self) -> Result<HashMap<String, Vec<String>>, String> {
+            Ok(HashMap::new())
+        }
+
+        fn form_data(&mut self) -> Result<HashMap<String, Vec<String>>, String> {
+            Ok(self.form.clone())
+        }
+
+        fn get_session_raw(&mut self, key: &str) -> Option<String> {
+            self.session.get(key).cloned()
+        }
+
+        fn set_session_raw(&mut self, key: &str, value: String) {
+            self.session.insert(key.to_string(), value);
+        }
+
+        fn clear_session(&mut self) {
+            self.session.clear();
+        }
+
+        fn context(&mut self) -> RspRequestContext {
+            self.ctx.clone()
+        }
+
+        fn get_state<T: 'static>(&self) -> Option<&T> {
+            None
+        }
+    }
+
+    type TestPage = PageEngine<TestState, (), NoPageAuth>;
+
+    fn html(outcome: &RspPageOutcome) -> &str {
+        match outcome.response {
+            RspPageResponse::Html(ref content) => content,
+            ref other => panic!("not a page: {:?}", std::mem::discriminant(other)),
+        }
+    }
+
+    /// The value of the cookie the response sets
+    fn set_cookie(outcome: &RspPageOutcome, name: &str) -> Option<String> {
+        outcome
+            .headers
+            .iter()
+            .filter(|(header, _)| header == "Set-Cookie")
+            .filter_map(|(_, value)| value.split(';').next()?.strip_prefix(&format!("{}=", name)))
+            .map(|value| value.to_string())
+            .next()
+    }
+
+    /// Post the form of the rendered page back, with the fields
+    fn post(rendered: &RspPageOutcome, fields: &[(&str, &str)]) -> TestRequest {
+        let (state_json, initial_state_json) = html(rendered).split_once('\n').unwrap();
+        let mut fields = fields.to_vec();
+        fields.push(("state_json", state_json));
+        fields.push(("initial_state_json", initial_state_json));
+        let mut req = TestRequest::new("POST", "/test", &fields);
+        let browser_key = set_cookie(rendered, state_codec::BROWSER_KEY_COOKIE).unwrap();
+        req.ctx.add_header("Cookie", &format!("{}={}", state_codec::BROWSER_KEY_COOKIE, browser_key));
+        req
+    }
+
+    #[test]
+    fn page_goes_through_the_lifecycle_in_order() {
+        let _secret = state_codec::with_test_secret(b"secret");
+        crate::engine::set_template_engine(TestEngine);
+        take_steps();
+
+        let rendered = TestPage::run(&mut TestRequest::new("GET", "/test", &[]));
+        assert_eq!(
+            take_steps(),
+            vec!["get_state", "event_handler unknown initial", "fill_data initial", "render test_page"]
+        );
+
+        let outcome = TestPage::run(&mut post(&rendered, &[("name", "changed"), ("submit_other", "x")]));
+        assert_eq!(
+            take_steps(),
+            vec!["get_state", "event_handler submit changed", "fill_data changed", "render test_page"]
+        );
+        assert!(html(&outcome).contains("changed"));
+    }
+
+    #[test]
+    fn redirect_skips_the_rendering() {
+        let _secret = state_codec::with_test_secret(b"secret");
+        crate::engine::set_template_engine(TestEngine);
+        let rendered = TestPage::run(&mut TestRequest::new("GET", "/test", &[]));
+        take_steps();
+
+        let outcome = TestPage::run(&mut post(&rendered, &[("submit_save", "Save")]));
+        assert_eq!(take_steps(), vec!["get_state", "event_handler submit initial"]);
+        assert!(matches!(outcome.response, RspPageResponse::Redirect(ref target) if target == "/done"));
+    }
+
+    #[test]
+    fn error_renders_the_error_page() {
+        crate::engine::set_template_engine(TestEngine);
+        take_steps();
+
+        let outcome = TestPage::run(&mut TestRequest::new("GET", "/missing", &[]));
+        assert_eq!(take_steps(), vec!["render error"]);
+        match outcome.response {
+            RspPageResponse::ErrorPage(HttpError::NotFound(_), ref content) => assert_eq!(content, "404 no such page"),
+            _ => panic!("not the error page"),
+        }
+    }
+
+    fn form(fields: &[(&str, &str)]) -> HashMap<String, Vec<String>> {
+        let mut form: HashMap<String, Vec<String>> = HashMap::new();
+        for (name, value) in fields {
+            form.entry(name.to_string()).or_default().push(value.to_string());
+        }
+        form
+    }
+
+    #[test]
+    fn only_inputs_amend_the_state() {
+        let mut state = serde_json::json!({
+            "txtName": "old",
+            "numAge": 1,
+            "cbAgree": true,
+            "msTags": [1],
+            "return_url": "/",
+            "gridLines": [{"txtProduct": "a", "note": "n"}],
+        });
+        let form = form(&[
+            ("txtName", "new"),
+            ("numAge", "42"),
+            ("cbAgree_sentinel", "false"),
+            ("msTags", "2"),
+            ("msTags", "3"),
+            ("return_url", "https://evil"),
+            ("gridLines__0__txtProduct", "b"),
+            ("gridLines__0__note", "evil"),
+        ]);
+        let is_input = |name: &str| {
+            ["txtName", "numAge", "cbAgree", "msTags", "gridLines__0__txtProduct"].contains(&name)
+        };
+        amend_json_value(&mut state, &form, &is_input);
+        assert_eq!(
+            state,
+            serde_json::json!({
+                "txtName": "new",
+                "numAge": 42,
+                "cbAgree": false,
+                "msTags": [2, 3],
+                "return_url": "/",
+                "gridLines": [{"txtProduct": "b", "note": "n"}],
+            })
+        );
+    }
+
+    /// Render the page, change the name and the note in the background,
+    /// and post the name changed by the user under the policy
+    fn post_over_background_change(policy: RspConflictPolicy) -> (RspPageOutcome, Option<(TestState, Vec<String>)>) {
+        let _secret = state_codec::with_test_secret(b"secret");
+        crate::engine::set_template_engine(TestEngine);
+        BACKGROUND.with(|background| *background.borrow_mut() = None);
+        let rendered = TestPage::run(&mut TestRequest::new("GET", "/test", &[]));
+
+        POLICY.with(|p| p.set(policy));
+        BACKGROUND.with(|background| {
+            *background.borrow_mut() = Some(TestState { name: "theirs".into(), note: "theirs".into() })
+        });
+        HANDLED.with(|handled| handled.borrow_mut().take());
+        let outcome = TestPage::run(&mut post(&rendered, &[("name", "mine"), ("submit_other", "x")]));
+        BACKGROUND.with(|background| *background.borrow_mut() = None);
+        POLICY.with(|p| p.set(RspConflictPolicy::Report));
+        (outcome, HANDLED.with(|handled| handled.borrow_mut().take()))
+    }
+
+    fn rendered_state(outcome: &RspPageOutcome) -> TestState {
+        let (state_json, _) = html(outcome).split_once('\n').unwrap();
+        let (_, json) = state_json.split_once('.').unwrap();
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn report_leaves_the_conflicts_to_the_event_handler() {
+        let (_, handled) = post_over_background_change(RspConflictPolicy::Report);
+        let (state, conflicts) = handled.unwrap();
+        assert_eq!(state, TestState { name: "mine".into(), note: "".into() });
+        assert_eq!(conflicts, vec!["name", "note"]);
+    }
+
+    #[test]
+    fn auto_merge_takes_over_the_untouched_fields() {
+        let (_, handled) = post_over_background_change(RspConflictPolicy::AutoMerge);
+        let (state, conflicts) = handled.unwrap();
+        assert_eq!(state, TestState { name: "mine".into(), note: "theirs".into() });
+        assert_eq!(conflicts, vec!["name"]);
+    }
+
+    #[test]
+    fn refuse_renders_the_page_again_without_the_event() {
+        let (outcome, handled) = post_over_background_change(RspConflictPolicy::Refuse);
+        assert!(handled.is_none());
+        assert_eq!(rendered_state(&outcome), TestState { name: "mine".into(), note: "theirs".into() });
+    }
+
+    #[test]
+    fn no_background_change_is_no_conflict() {
+        for policy in [RspConflictPolicy::Report, RspConflictPolicy::AutoMerge, RspConflictPolicy::Refuse] {
+            let _secret = state_codec::with_test_secret(b"secret");
+            crate::engine::set_template_engine(TestEngine);
+            let rendered = TestPage::run(&mut TestRequest::new("GET", "/test", &[]));
+            POLICY.with(|p| p.set(policy));
+            let _ = TestPage::run(&mut post(&rendered, &[("name", "mine"), ("submit_other", "x")]));
+            POLICY.with(|p| p.set(RspConflictPolicy::Report));
+            let (state, conflicts) = HANDLED.with(|handled| handled.borrow_mut().take()).unwrap();
+            assert_eq!(state.name, "mine");
+            assert!(conflicts.is_empty());
+        }
+    }
+
+    #[test]
+    fn ascii_filename_goes_as_is() {
+        assert_eq!(content_disposition("report 2024.csv"), "attachment; filename=\"report 2024.csv\"");
+    }
+
+    #[test]
+    fn other_filenames_get_an_ascii_fallback_and_filename_star() {
+        assert_eq!(
+            content_disposition("Übersicht €.pdf"),
+            "attachment; filename=\"_bersicht _.pdf\"; filename*=UTF-8''%C3%9Cbersicht%20%E2%82%AC.pdf"
+        );
+    }
+
+    #[test]
+    fn quotes_and_line_breaks_are_stripped() {
+        let header = content_disposition("a\"b\\c\r\nSet-Cookie: x.txt");
+        assert_eq!(
+            header,
+            "attachment; filename=\"a_b_c__Set-Cookie: x.txt\"; filename*=UTF-8''a%22b%5Cc%0D%0ASet-Cookie%3A%20x.txt"
+        );
+        assert!(!header.contains('\r') && !header.contains('\n'));
     }
 }
@@ -0,0 +1,301 @@
+/// The template engines the pages are rendered with
+///
+/// The data of a page is engine neutral - a JSON tree in `RspTemplateData` -
+/// and the `RspTemplateEngine` turns a template and the data into the page.
+/// Mustache is the default. With the `minijinja` feature, the pages can be
+/// rendered by MiniJinja instead, which has conditionals and filters:
+///
+/// ```ignore
+/// rsp10::set_template_engine(rsp10::MiniJinjaEngine::new());
+/// ```
+///
+/// The templates of an engine are the files with its extension under the
+/// template root - `teststate.mustache` for Mustache, `teststate.jinja` for MiniJinja.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::Serialize;
+use serde_json::Value;
+
+pub type RspTemplateMap = serde_json::Map<String, Value>;
+
+/// Insert the value into the map, as null if it can not be serialized
+pub fn insert_template_value<K: Into<String>, T: Serialize + ?Sized>(map: &mut RspTemplateMap, key: K, value: &T) {
+    map.insert(key.into(), serde_json::to_value(value).unwrap_or(Value::Null));
+}
+
+type RspLambdaFn = dyn FnMut(String) -> String + Send;
+type RspLambdaFn2 = dyn FnMut(String, &mut dyn FnMut(String) -> String) -> String + Send;
+
+/// A Mustache lambda - the other engines do not see them, MiniJinja warns about them
+#[derive(Clone)]
+enum RspLambda {
+    Fn(Arc<Mutex<Box<RspLambdaFn>>>),
+    Fn2(Arc<Mutex<Box<RspLambdaFn2>>>),
+}
+
+/// The data a template is rendered with
+#[derive(Clone, Default)]
+pub struct RspTemplateData {
+    pub values: RspTemplateMap,
+    /// The values that are rendered HTML, not to be escaped again
+    html: Vec<String>,
+    lambdas: Vec<(String, RspLambda)>,
+}
+
+impl fmt::Debug for RspTemplateData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lambdas: Vec<&String> = self.lambdas.iter().map(|(name, _)| name).collect();
+        f.debug_struct("RspTemplateData")
+            .field("values", &self.values)
+            .field("html", &self.html)
+            .field("lambdas", &lambdas)
+            .finish()
+    }
+}
+
+impl RspTemplateData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), serde_json::Error> {
+        self.values.insert(key.to_string(), serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    pub fn insert_value(&mut self, key: &str, value: Value) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    /// Rendered HTML, like the page in the slot of its layout - MiniJinja does not
+    /// escape it, with Mustache it still needs the triple `{{{name}}}`
+    pub fn insert_html(&mut self, key: &str, html: String) {
+        self.values.insert(key.to_string(), Value::String(html));
+        if !self.html.iter().any(|k| k == key) {
+            self.html.push(key.to_string());
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    /// A Mustache lambda, `{{#name}}text{{/name}}` renders as f("text")
+    pub fn insert_fn<F>(&mut self, key: &str, f: F)
+    where
+        F: FnMut(String) -> String + Send + 'static,
+    {
+        self.lambdas.push((key.to_string(), RspLambda::Fn(Arc::new(Mutex::new(Box::new(f))))));
+    }
+
+    /// A Mustache lambda that also gets the function rendering the text
+    pub fn insert_fn2<F>(&mut self, key: &str, f: F)
+    where
+        F: FnMut(String, &mut dyn FnMut(String) -> String) -> String + Send + 'static,
+    {
+        self.lambdas.push((key.to_string(), RspLambda::Fn2(Arc::new(Mutex::new(Box::new(f))))));
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RspTemplateError {
+    pub template: String,
+    pub message: String,
+}
+
+impl RspTemplateError {
+    pub fn new<E: fmt::Display>(template: &str, e: E) -> Self {
+        RspTemplateError {
+            template: template.to_string(),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RspTemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.template, self.message)
+    }
+}
+
+pub trait RspTemplateEngine: Send + Sync {
+    /// The extension of the template files, without the dot
+    fn extension(&self) -> &'static str;
+
+    /// Compile the template ahead of its use, to find the errors early
+    fn load(&self, name: &str) -> Result<(), RspTemplateError>;
+
+    fn render(&self, name: &str, data: &RspTemplateData) -> Result<String, RspTemplateError>;
+
+    /// Forget the compiled templates
+    fn clear(&self) {}
+}
+
+static TEMPLATE_ENGINE: RwLock<Option<Arc<dyn RspTemplateEngine>>> = RwLock::new(None);
+
+/// Render the pages with the engine instead of Mustache
+pub fn set_template_engine<E: RspTemplateEngine + 'static>(engine: E) {
+    if let Ok(mut lock) = TEMPLATE_ENGINE.write() {
+        *lock = Some(Arc::new(engine));
+    }
+}
+
+/// The engine the pages are rendered with
+pub fn template_engine() -> Arc<dyn RspTemplateEngine> {
+    if let Ok(lock) = TEMPLATE_ENGINE.read() {
+        if let Some(ref engine) = *lock {
+            return engine.clone();
+        }
+    }
+    Arc::new(MustacheEngine)
+}
+
+/// The default engine, with the templates compiled by `template_cache`
+pub struct MustacheEngine;
+
+impl MustacheEngine {
+    fn data(data: &RspTemplateData) -> Result<mustache::Data, String> {
+        let mut builder = mustache::MapBuilder::new();
+        for (key, value) in data.values.iter() {
+            builder = builder.insert(key.clone(), value).map_err(|e| format!("{:?}", e))?;
+        }
+        for (key, lambda) in data.lambdas.iter() {
+            builder = match lambda.clone() {
+                RspLambda::Fn(f) => builder.insert_fn(key.clone(), move |s| (f.lock().unwrap())(s)),
+                RspLambda::Fn2(f) => builder.insert_fn2(key.clone(), move |s, render: &mut dyn FnMut(String) -> String| {
+                    (f.lock().unwrap())(s, render)
+                }),
+            };
+        }
+        Ok(builder.build())
+    }
+}
+
+/// The Display of mustache::Error loses the message of an I/O error, e.g. the missing template
+fn compile_error(name: &str, e: mustache::Error) -> RspTemplateError {
+    match e {
+        mustache::Error::Io(e) => RspTemplateError::new(name, e),
+        e => RspTemplateError::new(name, e),
+    }
+}
+
+impl RspTemplateEngine for MustacheEngine {
+    fn extension(&self) -> &'static str {
+        "mustache"
+    }
+
+    fn load(&self, name: &str) -> Result<(), RspTemplateError> {
+        crate::cached_template(name).map(|_| ()).map_err(|e| compile_error(name, e))
+    }
+
+    fn render(&self, name: &str, data: &RspTemplateData) -> Result<String, RspTemplateError> {
+        let template = crate::cached_template(name).map_err(|e| compile_error(name, e))?;
+        let data = Self::data(data).map_err(|e| RspTemplateError::new(name, e))?;
+        let mut bytes = vec![];
+        template
+            .render_data(&mut bytes, &data)
+            .map_err(|e| RspTemplateError::new(name, e))?;
+        String::from_utf8(bytes).map_err(|e| RspTemplateError::new(name, e))
+    }
+
+    fn clear(&self) {
+        crate::template_cache::clear_template_cache();
+    }
+}
+
+#[cfg(feature = "minijinja")]
+pub use self::minijinja_engine::MiniJinjaEngine;
+
+#[cfg(feature = "minijinja")]
+mod minijinja_engine {
+    use super::*;
+    use std::time::SystemTime;
+
+    /// MiniJinja, with the templates loaded from the template root (or the embedded ones).
+    /// The templates are HTML-escaped, and reloaded on changes like the Mustache ones.
+    /// The `html/*.jinja` element templates render the element in the `e` variable, see
+    /// `{% with e = ddColor %}{% include "html/select" %}{% endwith %}` in the README.
+    pub struct MiniJinjaEngine {
+        env: RwLock<minijinja::Environment<'static>>,
+        mtime: Mutex<Option<SystemTime>>,
+    }
+
+    impl MiniJinjaEngine {
+        pub fn new() -> Self {
+            let mut env = minijinja::Environment::new();
+            env.set_auto_escape_callback(|_| minijinja::AutoEscape::Html);
+            env.set_loader(|name| match crate::resources::template_source(name) {
+                Ok(source) => Ok(Some(source)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string())),
+            });
+            MiniJinjaEngine {
+                env: RwLock::new(env),
+                mtime: Mutex::new(None),
+            }
+        }
+
+        /// The environment, to add the filters, functions and globals
+        pub fn environment_mut(&mut self) -> &mut minijinja::Environment<'static> {
+            self.env.get_mut().unwrap()
+        }
+
+        fn context(data: &RspTemplateData) -> minijinja::Value {
+            let context: std::collections::BTreeMap<&str, minijinja::Value> = data
+                .values
+                .iter()
+                .map(|(key, value)| match value {
+                    Value::String(html) if data.html.contains(key) => {
+                        (key.as_str(), minijinja::Value::from_safe_string(html.clone()))
+                    }
+                    _ => (key.as_str(), minijinja::Value::from_serialize(value)),
+                })
+                .collect();
+            minijinja::Value::from(context)
+        }
+
+        fn reload_changed(&self) {
+            if !crate::template_cache::template_reload() {
+                return;
+            }
+            let newest = crate::template_cache::templates_mtime();
+            let mut mtime = self.mtime.lock().unwrap();
+            if newest > *mtime {
+                if mtime.is_some() {
+                    debug!("Templates changed, reloading");
+                    self.env.write().unwrap().clear_templates();
+                }
+                *mtime = newest;
+            }
+        }
+    }
+
+    impl RspTemplateEngine for MiniJinjaEngine {
+        fn extension(&self) -> &'static str {
+            "jinja"
+        }
+
+        fn load(&self, name: &str) -> Result<(), RspTemplateError> {
+            self.reload_changed();
+            let env = self.env.read().unwrap();
+            env.get_template(name).map(|_| ()).map_err(|e| RspTemplateError::new(name, e))
+        }
+
+        fn render(&self, name: &str, data: &RspTemplateData) -> Result<String, RspTemplateError> {
+            if !data.lambdas.is_empty() {
+                let lambdas: Vec<&String> = data.lambdas.iter().map(|(name, _)| name).collect();
+                warn!("{}: the Mustache lambdas {:?} are left out, MiniJinja does not have them", name, lambdas);
+            }
+            self.reload_changed();
+            let env = self.env.read().unwrap();
+            let template = env.get_template(name).map_err(|e| RspTemplateError::new(name, e))?;
+            template.render(Self::context(data)).map_err(|e| RspTemplateError::new(name, e))
+        }
+
+        fn clear(&self) {
+            self.env.write().unwrap().clear_templates();
+        }
+    }
+}
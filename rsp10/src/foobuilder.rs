@@ -1,9 +1,10 @@
-use mustache::MapBuilder;
+use crate::engine::{insert_template_value, RspTemplateData, RspTemplateMap};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-type FooClosure = Box<dyn Fn(MapBuilder) -> MapBuilder>;
+type FooClosure = Box<dyn Fn(&mut RspTemplateData)>;
+type FooRowClosure = Box<dyn Fn(&mut RspTemplateMap)>;
 
 struct FooItemBuilder {
     name: String,
@@ -19,7 +20,7 @@ impl FooItemBuilder {
     }
     fn add<F>(&mut self, f: F)
     where
-        F: Fn(MapBuilder) -> MapBuilder,
+        F: Fn(&mut RspTemplateData),
         F: 'static,
     {
         self.part = Some(Box::new(f));
@@ -33,7 +34,7 @@ impl FooItemBuilder {
         self.add({
             let name = self.name.to_owned();
             let data = data.to_owned();
-            move |x: MapBuilder| x.insert(name.clone(), &data.borrow().clone()).unwrap()
+            move |x: &mut RspTemplateData| insert_template_value(&mut x.values, name.clone(), &*data.borrow())
         })
     }
 
@@ -41,10 +42,9 @@ impl FooItemBuilder {
     where
         F: FnMut(String) -> String + Clone + Send + Sync + 'static,
     {
-        let fn2 = RefCell::new(Box::new(f));
         self.add({
             let name = self.name.to_owned();
-            move |x: MapBuilder| x.insert_fn(name.clone(), fn2.clone().into_inner())
+            move |x: &mut RspTemplateData| x.insert_fn(&name, f.clone())
         })
     }
 
@@ -52,10 +52,9 @@ impl FooItemBuilder {
     where
         F: FnMut(String, &mut (dyn FnMut(String) -> String)) -> String + Clone + Send + Sync + 'static,
     {
-        let fn2 = RefCell::new(Box::new(f));
         self.add({
             let name = self.name.to_owned();
-            move |x: MapBuilder| x.insert_fn2(name.clone(), fn2.clone().into_inner())
+            move |x: &mut RspTemplateData| x.insert_fn2(&name, f.clone())
         })
     }
 
@@ -68,17 +67,16 @@ impl FooItemBuilder {
         self.add_field(&rc);
     }
 
-    fn build(self, map_builder: MapBuilder) -> MapBuilder {
-        match self.part {
-            None => map_builder,
-            Some(part) => part(map_builder),
+    fn build(self, data: &mut RspTemplateData) {
+        if let Some(part) = self.part {
+            part(data);
         }
     }
 }
 
 pub struct FooVecBuilder {
     name: String,
-    parts: Vec<Vec<FooClosure>>,
+    parts: Vec<Vec<FooRowClosure>>,
 }
 
 impl FooVecBuilder {
@@ -91,7 +89,7 @@ impl FooVecBuilder {
 
     fn add<F>(&mut self, f: F)
     where
-        F: Fn(MapBuilder) -> MapBuilder,
+        F: Fn(&mut RspTemplateMap),
         F: 'static,
     {
         self.parts.push(vec![Box::new(f)]);
@@ -99,11 +97,11 @@ impl FooVecBuilder {
 
     fn add_at<F>(&mut self, i: usize, f: F)
     where
-        F: Fn(MapBuilder) -> MapBuilder,
+        F: Fn(&mut RspTemplateMap),
         F: 'static,
     {
         while self.parts.len() + 1 < i {
-            self.parts.push(vec![Box::new(|x| x)])
+            self.parts.push(vec![Box::new(|_| {})])
         }
         if self.parts.len() > i {
             self.parts[i].push(Box::new(f));
@@ -120,7 +118,7 @@ impl FooVecBuilder {
         self.add_at(i, {
             let name = name.to_owned();
             let data = data.to_owned();
-            move |x: MapBuilder| x.insert(name.clone(), &data.borrow().clone()).unwrap()
+            move |x: &mut RspTemplateMap| insert_template_value(x, name.clone(), &*data.borrow())
         })
     }
 
@@ -141,14 +139,17 @@ impl FooVecBuilder {
         self.add_field_at(self.parts.len(), name, data);
     }
 
-    fn build(self, map_builder: MapBuilder) -> MapBuilder {
-        let map_builder = map_builder.insert_vec(&self.name, |builder| {
-            let mut builder = builder;
-            self.parts.iter().fold(builder, |builder, getter| {
-                builder.push_map(|m| getter.iter().fold(m, |m, g| g(m)))
+    fn build(self, data: &mut RspTemplateData) {
+        let rows = self
+            .parts
+            .iter()
+            .map(|getters| {
+                let mut row = RspTemplateMap::new();
+                getters.iter().for_each(|g| g(&mut row));
+                serde_json::Value::Object(row)
             })
-        });
-        map_builder
+            .collect();
+        data.insert_value(&self.name, serde_json::Value::Array(rows));
     }
 }
 
@@ -184,6 +185,17 @@ impl FooMapBuilder {
         }
     }
 
+    /// The vector builder of the name, for filling it in outside of a closure
+    pub fn vector_builder(&mut self, name: &str) -> &mut FooVecBuilder {
+        let builder = self.builders.entry(name.to_string());
+        let builder = builder.or_insert_with(|| FooAnyBuilder::Vector(FooVecBuilder::new(name)));
+        if let FooAnyBuilder::Vector(b) = builder {
+            b
+        } else {
+            panic!("wrong builder");
+        }
+    }
+
     pub fn item<T>(&mut self, name: &str, data: &Rc<RefCell<T>>)
     where
         T: serde::Serialize + Clone,
@@ -238,19 +250,19 @@ impl FooMapBuilder {
         }
     }
 
-    pub fn build(self, map_builder: MapBuilder) -> MapBuilder {
-        let mut map_builder = map_builder;
+    pub fn build(self, data: RspTemplateData) -> RspTemplateData {
+        let mut data = data;
         for (k, v) in self.builders {
             match v {
                 FooAnyBuilder::Item(ht) => {
-                    map_builder = ht.build(map_builder);
+                    ht.build(&mut data);
                 }
                 FooAnyBuilder::Vector(ht) => {
-                    map_builder = ht.build(map_builder);
+                    ht.build(&mut data);
                 }
                 _ => {}
             }
         }
-        map_builder
+        data
     }
 }
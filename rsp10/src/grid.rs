@@ -0,0 +1,259 @@
+/// Editable grids: a Vec of row structs in the page state, rendered as repeating rows
+///
+/// The inputs of a row are named `grid__index__field`, so that they are amended
+/// into the right row when the form comes back. The row struct implements
+/// `RspRow`, usually with `#[derive(RspRow)]`, and the field of the state is
+/// filled in with `rsp10_grid!` - or by the derive, for the `gridXXX` fields.
+///
+/// In the template, each row of the grid is rendered from its own data:
+///
+/// ```ignore
+/// {{#gridLines}}
+/// <tr><td>{{#txtProduct}}{{> html/text}}{{/txtProduct}}</td><td>{{> html/row_remove}}</td></tr>
+/// {{/gridLines}}
+/// <input type="submit" name="submit_gridLines__add" value="Add a line">
+/// ```
+///
+/// The `submit_gridXXX__add` and `submit_gridXXX__N__remove` buttons are handled
+/// by the framework, without calling the event handler.
+
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use crate::conflict::RspFieldConflict;
+use crate::core::RspEvent;
+use crate::foobuilder::FooVecBuilder;
+use crate::validation::{RspFieldError, RspValidator};
+
+/// A row of an editable grid
+pub trait RspRow:
+    serde::Serialize + serde::de::DeserializeOwned + Clone + Default + PartialEq + Debug + 'static
+{
+    /// Put the inputs of the row into the row data, return whether the row was modified
+    fn fill_row(ri: &mut RspRowInfo<Self>, gd: &mut RspRowData) -> bool;
+
+    /// Check the rules of the row fields, the errors are named by `row_field_name()`
+    fn validate_row(&self, _row_prefix: &str, _v: &mut RspValidator) {}
+
+    /// The fields with an input element, the only ones of the row the posted form may change
+    fn input_fields() -> &'static [&'static str] {
+        &[]
+    }
+}
+
+/// The input fields of the rows of the grid field the accessor returns,
+/// for `RspStateMeta::grid_input_fields` - only the type of the rows matters
+pub fn row_input_fields<S, R: RspRow>(_field: fn(&S) -> &Vec<R>) -> &'static [&'static str] {
+    R::input_fields()
+}
+
+/// The full name of a field of a row, as used for the inputs and in the errors
+pub fn row_field_name(grid: &str, index: usize, field: &str) -> String {
+    format!("{}__{}__{}", grid, index, field)
+}
+
+/// What the element macros need to fill in a row, like RspInfo for the page
+pub struct RspRowInfo<'a, R> {
+    pub grid: &'a str,
+    pub index: usize,
+    pub state: R,
+    /// The row as it was when the page was rendered, a default one for a new row
+    pub initial_state: R,
+    pub conflicts: &'a [RspFieldConflict],
+    pub errors: &'a [RspFieldError],
+}
+
+impl<'a, R: RspRow> RspRowInfo<'a, R> {
+    pub fn new(
+        grid: &'a str,
+        index: usize,
+        state: R,
+        initial_state: Option<R>,
+        conflicts: &'a [RspFieldConflict],
+        errors: &'a [RspFieldError],
+    ) -> Self {
+        Self {
+            grid,
+            index,
+            state,
+            initial_state: initial_state.unwrap_or_default(),
+            conflicts,
+            errors,
+        }
+    }
+
+    pub fn element_id(&self, field: &str) -> String {
+        row_field_name(self.grid, self.index, field)
+    }
+
+    /// Whether this field of this row was changed in the background
+    pub fn has_conflict(&self, field: &str) -> bool {
+        let pointer = format!("/{}/{}", self.index, field);
+        self.conflicts
+            .iter()
+            .any(|c| c.field == self.grid && c.initial.pointer(&pointer) != c.current.pointer(&pointer))
+    }
+
+    pub fn field_error(&self, field: &str) -> Option<&str> {
+        let name = self.element_id(field);
+        self.errors
+            .iter()
+            .find(|e| e.field == name)
+            .map(|e| e.message.as_str())
+    }
+}
+
+/// The template data of one row, used by the element macros in place of RspDataBuilder
+pub struct RspRowData<'a> {
+    vb: &'a mut FooVecBuilder,
+    index: usize,
+}
+
+impl<'a> RspRowData<'a> {
+    /// Every row gets its index, and the prefix for naming its own buttons
+    pub fn new(vb: &'a mut FooVecBuilder, grid: &str, index: usize) -> Self {
+        vb.insert_data_at(index, "row_index", &index);
+        vb.insert_data_at(index, "row_prefix", &format!("{}__{}", grid, index));
+        Self { vb, index }
+    }
+
+    pub fn item<T>(&mut self, name: &str, data: &Rc<RefCell<T>>)
+    where
+        T: serde::Serialize + Clone + 'static,
+    {
+        self.vb.add_field_at(self.index, name, data);
+    }
+
+    pub fn insert<T>(&mut self, name: &str, data: &T)
+    where
+        T: serde::Serialize + Clone + 'static,
+    {
+        self.vb.insert_data_at(self.index, name, data);
+    }
+}
+
+impl RspEvent {
+    /// The row and the target within it, for an event on a row of the grid
+    pub fn row_target(&self, grid: &str) -> Option<(usize, &str)> {
+        let target = self.target.trim_start_matches('_');
+        let rest = target.strip_prefix(grid)?.strip_prefix("__")?;
+        let (index, row_target) = rest.split_once("__")?;
+        // Only the plain digits the row names are made of, not e.g. "+1"
+        if !index.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some((index.parse().ok()?, row_target))
+    }
+}
+
+/// Add or remove a row if the event asks for it, return whether it did
+pub fn grid_event<R: RspRow>(rows: &mut Vec<R>, grid: &str, event: &RspEvent) -> bool {
+    if event.event != "submit" {
+        return false;
+    }
+    if event.target.trim_start_matches('_') == format!("{}__add", grid) {
+        rows.push(R::default());
+        return true;
+    }
+    match event.row_target(grid) {
+        Some((index, "remove")) if index < rows.len() => {
+            rows.remove(index);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Check the rules of each row of the grid
+pub fn validate_rows<R: RspRow>(rows: &[R], grid: &str, v: &mut RspValidator) {
+    for (i, row) in rows.iter().enumerate() {
+        row.validate_row(&format!("{}__{}", grid, i), v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    struct Line {
+        txtProduct: String,
+    }
+
+    impl RspRow for Line {
+        fn fill_row(_ri: &mut RspRowInfo<Self>, _gd: &mut RspRowData) -> bool {
+            false
+        }
+    }
+
+    fn line(product: &str) -> Line {
+        Line {
+            txtProduct: product.to_string(),
+        }
+    }
+
+    /// The event of a click on the submit button of this name
+    fn submit(button: &str) -> RspEvent {
+        let mut form = HashMap::new();
+        form.insert(button.to_string(), vec![String::new()]);
+        crate::core::extract_event(&form)
+    }
+
+    fn after(button: &str) -> (bool, Vec<Line>) {
+        let mut rows = vec![line("a"), line("b")];
+        let handled = grid_event(&mut rows, "gridLines", &submit(button));
+        (handled, rows)
+    }
+
+    #[test]
+    fn add_appends_a_default_row() {
+        assert_eq!(after("submit_gridLines__add"), (true, vec![line("a"), line("b"), line("")]));
+    }
+
+    #[test]
+    fn remove_drops_the_row() {
+        assert_eq!(after("submit_gridLines__0__remove"), (true, vec![line("b")]));
+        assert_eq!(after("submit_gridLines__1__remove"), (true, vec![line("a")]));
+    }
+
+    #[test]
+    fn malformed_and_out_of_range_targets_are_rejected() {
+        let unchanged = (false, vec![line("a"), line("b")]);
+        for button in [
+            "submit_gridLines__2__remove",
+            "submit_gridLines__99999999999999999999999__remove",
+            "submit_gridLines__-1__remove",
+            "submit_gridLines__+1__remove",
+            "submit_gridLines__x__remove",
+            "submit_gridLines____remove",
+            "submit_gridLines__0",
+            "submit_gridLines__0__edit",
+            "submit_gridLinesX__0__remove",
+            "submit_gridOther__add",
+            "submit_gridLines__add__more",
+        ] {
+            assert_eq!(after(button), unchanged, "{}", button);
+        }
+    }
+
+    #[test]
+    fn only_submit_events_change_the_rows() {
+        let mut rows = vec![line("a")];
+        let event = RspEvent {
+            event: "change".to_string(),
+            target: "gridLines__add".to_string(),
+        };
+        assert!(!grid_event(&mut rows, "gridLines", &event));
+        assert_eq!(rows.len(), 1);
+    }
+
+    #[test]
+    fn row_target_names_the_row_and_the_target() {
+        let event = submit("submit_gridLines__3__btnDetails");
+        assert_eq!(event.row_target("gridLines"), Some((3, "btnDetails")));
+        assert_eq!(event.row_target("grid"), None);
+        assert_eq!(submit("submit_gridLines__add").row_target("gridLines"), None);
+    }
+}
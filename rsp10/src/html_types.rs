@@ -1,3 +1,4 @@
+use crate::engine::{insert_template_value, RspTemplateMap};
 use serde::Serialize;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -17,13 +18,11 @@ impl HtmlForm {
         self.list.push(input.clone());
     }
     /*
-    pub fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder {
-        let mut data = data;
+    pub fn template_data(&self, data: &mut RspTemplateMap) {
         for elt in &self.list {
             println!("elt: {:?}", &elt);
-            data = elt.borrow().mustache_render(data);
+            elt.borrow().template_data(data);
         }
-        data
     }
     */
 }
@@ -44,19 +43,17 @@ impl HtmlFormVector {
 }
 
 impl HtmlInput for HtmlFormVector {
-    fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder {
-        let mut data = data;
-        data = data.insert_vec(format!("{}", self.name), |mut vdata| {
-            for (i, ref aform) in self.forms.iter().enumerate() {
-                vdata = vdata.push_map(|mut data| {
-                    data = aform.mustache_render(data);
-                    data
-                });
-            }
-            vdata
-        });
-
-        data
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        let forms = self
+            .forms
+            .iter()
+            .map(|aform| {
+                let mut form_data = RspTemplateMap::new();
+                aform.template_data(&mut form_data);
+                serde_json::Value::Object(form_data)
+            })
+            .collect();
+        data.insert(format!("{}", self.name), serde_json::Value::Array(forms));
     }
 }
 
@@ -69,17 +66,15 @@ pub trait HtmlInput: Debug {
             format!("{}", name_vec[2])
         }
     }
-    fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder;
+    fn template_data(&self, data: &mut RspTemplateMap);
 }
 
 impl HtmlInput for HtmlForm {
-    fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder {
-        let mut data = data;
+    fn template_data(&self, data: &mut RspTemplateMap) {
         for elt in &self.list {
             // println!("elt: {:?}", &elt);
-            data = elt.borrow().mustache_render(data);
+            elt.borrow().template_data(data);
         }
-        data
     }
 }
 
@@ -88,21 +83,21 @@ pub struct HtmlText {
     pub id: String,
     pub value: String,
     pub labeltext: String,
+    pub placeholder: String,
     pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
     pub hidden: bool,
     pub disabled: bool,
 }
 
 impl HtmlInput for HtmlText {
-    fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder {
-        let mut data = data.insert(self.get_sid(&self.id), &self).unwrap();
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
         if self.value != "false".to_string() && self.value != "0".to_string() {
             // if it looks like a false, insert a boolean
-            data = data
-                .insert(format!("{}_is_true", self.get_sid(&self.id)), &self.value)
-                .unwrap();
+            insert_template_value(data, format!("{}_is_true", self.get_sid(&self.id)), &self.value);
         }
-        data
     }
 }
 
@@ -117,8 +112,8 @@ pub struct HtmlButton {
 }
 
 impl HtmlInput for HtmlButton {
-    fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder {
-        data.insert(self.get_sid(&self.id), &self).unwrap()
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
     }
 }
 
@@ -128,13 +123,15 @@ pub struct HtmlCheck {
     pub labeltext: String,
     pub checked: bool,
     pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
     pub hidden: bool,
     pub disabled: bool,
 }
 
 impl HtmlInput for HtmlCheck {
-    fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder {
-        data.insert(self.get_sid(&self.id), &self).unwrap()
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
     }
 }
 
@@ -150,9 +147,9 @@ impl<T> HtmlInput for HtmlSelectItem<T>
 where
     T: PartialEq + Clone + Debug + ToString + Serialize,
 {
-    fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder {
-        let data = data.insert("item_number".to_string(), &self.i).unwrap();
-        data.insert(format!("id_{}", self.i), &self).unwrap()
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, "item_number", &self.i);
+        insert_template_value(data, format!("id_{}", self.i), &self);
     }
 }
 
@@ -178,20 +175,65 @@ impl<T> HtmlInput for HtmlValueContainer<T>
 where
     T: PartialEq + Clone + Debug + Serialize,
 {
-    fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder {
+    fn template_data(&self, data: &mut RspTemplateMap) {
         let mysid = format!("{}", self.get_sid(&self.id));
         // println!("Inserting: {} = {:#?}", &mysid, &self.value);
-        data.insert(mysid, &self.value).unwrap()
+        insert_template_value(data, mysid, &self.value);
     }
 }
 
 use std::fmt::Debug;
+/// Add an item to the items of a select, radio or multiselect
+fn push_item<T>(items: &mut Vec<HtmlSelectItem<T>>, user_label: &str, value: T)
+where
+    T: PartialEq + Clone + Debug + ToString + Serialize,
+{
+    let i = HtmlSelectItem::<T> {
+        user_label: user_label.into(),
+        value,
+        selected: false,
+        i: items.len(),
+    };
+    items.push(i);
+}
+
+/// Select the item with the value, or the first one if no item has it - the value
+/// is then reset to the first item. Returns whether an item is selected.
+fn select_one<T>(items: &mut [HtmlSelectItem<T>], selected_value: &mut T) -> bool
+where
+    T: PartialEq + Clone + Debug + ToString + Serialize,
+{
+    let mut found = false;
+    for item in items.iter_mut() {
+        item.selected = item.value == *selected_value;
+        found = found || item.selected;
+    }
+    if !found && !items.is_empty() {
+        items[0].selected = true;
+        *selected_value = items[0].value.clone();
+        found = true;
+    }
+    found
+}
+
+fn select_many<T>(items: &mut [HtmlSelectItem<T>], selected_values: &[T])
+where
+    T: PartialEq + Clone + Debug + ToString + Serialize,
+{
+    for item in items.iter_mut() {
+        item.selected = selected_values.contains(&item.value);
+    }
+}
+
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct HtmlSelect<T: PartialEq + Clone + Debug + ToString + Serialize> {
     pub id: String,
+    pub labeltext: String,
     pub items: Vec<HtmlSelectItem<T>>,
     pub selected_value: T,
     pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
     pub hidden: bool,
     pub disabled: bool,
 }
@@ -200,8 +242,8 @@ impl<T> HtmlInput for HtmlSelect<T>
 where
     T: PartialEq + Clone + Debug + ToString + Serialize,
 {
-    fn mustache_render(&self, data: mustache::MapBuilder) -> mustache::MapBuilder {
-        data.insert(self.get_sid(&self.id), &self).unwrap()
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
     }
 }
 
@@ -210,52 +252,298 @@ where
     T: std::cmp::PartialEq + std::clone::Clone + Debug + ToString + Serialize,
 {
     pub fn item(self: &mut HtmlSelect<T>, user_label: &str, value: T) {
-        let i = HtmlSelectItem::<T> {
-            user_label: user_label.into(),
-            value,
-            selected: false,
-            i: self.items.len(),
-        };
-        self.items.push(i);
+        push_item(&mut self.items, user_label, value);
     }
 
+    /// A value not among the items selects the first one, and is reset to it
     pub fn set_selected_value(self: &mut HtmlSelect<T>, selected_value: &mut T) {
-        let mut found = false;
-        // println!("Setting selected value: {:?}", &selected_value);
-        for mut item in &mut self.items {
-            if item.value == *selected_value {
-                item.selected = true;
-                found = true;
-                self.selected_value = selected_value.clone();
-            } else {
-                item.selected = false;
-            }
-            // println!("Item: {:?}", &item);
-        }
-        if !found {
-            if !self.items.is_empty() {
-                self.items[0].selected = true;
-                *selected_value = self.items[0].value.clone();
-                self.selected_value = selected_value.clone();
-
-            // println!("Setting selected value to {:?}", &selected_value);
-            } else {
-                // println!("Can not set default selected value");
-            }
-        } else {
-            // println!("Found in items, selected value not reset");
+        if select_one(&mut self.items, selected_value) {
+            self.selected_value = selected_value.clone();
         }
     }
 }
 
 impl HtmlSelect<String> {
     pub fn item1(self: &mut HtmlSelect<String>, user_label: &str) {
-        let i = HtmlSelectItem::<String> {
-            i: self.items.len(),
-            user_label: user_label.into(),
-            value: user_label.into(),
-            selected: false,
-        };
-        self.items.push(i);
+        self.item(user_label, user_label.into());
+    }
+}
+
+/// The same as the select, shown as a group of radio buttons
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct HtmlRadio<T: PartialEq + Clone + Debug + ToString + Serialize> {
+    pub id: String,
+    pub labeltext: String,
+    pub items: Vec<HtmlSelectItem<T>>,
+    pub selected_value: T,
+    pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
+    pub hidden: bool,
+    pub disabled: bool,
+}
+
+impl<T> HtmlInput for HtmlRadio<T>
+where
+    T: PartialEq + Clone + Debug + ToString + Serialize,
+{
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
+    }
+}
+
+impl<T> HtmlRadio<T>
+where
+    T: std::cmp::PartialEq + std::clone::Clone + Debug + ToString + Serialize,
+{
+    pub fn item(self: &mut HtmlRadio<T>, user_label: &str, value: T) {
+        push_item(&mut self.items, user_label, value);
+    }
+
+    /// Same as for the select: a value not among the items selects the first one
+    pub fn set_selected_value(self: &mut HtmlRadio<T>, selected_value: &mut T) {
+        if select_one(&mut self.items, selected_value) {
+            self.selected_value = selected_value.clone();
+        }
+    }
+}
+
+impl HtmlRadio<String> {
+    pub fn item1(self: &mut HtmlRadio<String>, user_label: &str) {
+        self.item(user_label, user_label.into());
+    }
+}
+
+/// How a state value is shown in an input element
+pub trait RspInputValue {
+    fn to_input_value(&self) -> String;
+
+    /// The type attribute of the input the value needs, where it matters
+    fn input_type() -> &'static str
+    where
+        Self: Sized,
+    {
+        "text"
+    }
+}
+
+impl RspInputValue for String {
+    fn to_input_value(&self) -> String {
+        self.clone()
+    }
+}
+
+impl RspInputValue for bool {
+    fn to_input_value(&self) -> String {
+        self.to_string()
+    }
+}
+
+macro_rules! impl_input_value_number {
+    ($($t: ty),*) => {
+        $(
+            impl RspInputValue for $t {
+                fn to_input_value(&self) -> String {
+                    self.to_string()
+                }
+                fn input_type() -> &'static str {
+                    "number"
+                }
+            }
+        )*
+    };
+}
+
+impl_input_value_number!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+impl RspInputValue for chrono::NaiveDate {
+    fn to_input_value(&self) -> String {
+        self.format("%Y-%m-%d").to_string()
+    }
+    fn input_type() -> &'static str {
+        "date"
+    }
+}
+
+impl RspInputValue for chrono::NaiveDateTime {
+    fn to_input_value(&self) -> String {
+        self.format("%Y-%m-%dT%H:%M:%S%.f").to_string()
+    }
+    fn input_type() -> &'static str {
+        "datetime-local"
+    }
+}
+
+impl<V: RspInputValue> RspInputValue for Option<V> {
+    fn to_input_value(&self) -> String {
+        self.as_ref().map(|v| v.to_input_value()).unwrap_or_default()
+    }
+    fn input_type() -> &'static str {
+        V::input_type()
+    }
+}
+
+pub fn input_type_of<V: RspInputValue>(_value: &V) -> &'static str {
+    V::input_type()
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct HtmlTextArea {
+    pub id: String,
+    pub value: String,
+    pub labeltext: String,
+    pub placeholder: String,
+    pub rows: Option<u32>,
+    pub cols: Option<u32>,
+    pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
+    pub hidden: bool,
+    pub disabled: bool,
+}
+
+impl HtmlInput for HtmlTextArea {
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
+    }
+}
+
+/// Numeric input, min/max/step are left out of the markup when empty
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct HtmlNumber {
+    pub id: String,
+    pub value: String,
+    pub labeltext: String,
+    pub placeholder: String,
+    pub min: String,
+    pub max: String,
+    pub step: String,
+    pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
+    pub hidden: bool,
+    pub disabled: bool,
+}
+
+impl HtmlInput for HtmlNumber {
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
+    }
+}
+
+/// Date picker: input_type is "date" for a NaiveDate and "datetime-local" for a NaiveDateTime
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct HtmlDate {
+    pub id: String,
+    pub value: String,
+    pub labeltext: String,
+    pub input_type: String,
+    pub min: String,
+    pub max: String,
+    pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
+    pub hidden: bool,
+    pub disabled: bool,
+}
+
+impl HtmlInput for HtmlDate {
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct HtmlEmail {
+    pub id: String,
+    pub value: String,
+    pub labeltext: String,
+    pub placeholder: String,
+    pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
+    pub hidden: bool,
+    pub disabled: bool,
+}
+
+impl HtmlInput for HtmlEmail {
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct HtmlUrl {
+    pub id: String,
+    pub value: String,
+    pub labeltext: String,
+    pub placeholder: String,
+    pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
+    pub hidden: bool,
+    pub disabled: bool,
+}
+
+impl HtmlInput for HtmlUrl {
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct HtmlHidden {
+    pub id: String,
+    pub value: String,
+}
+
+impl HtmlInput for HtmlHidden {
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
+    }
+}
+
+/// Any number of items selected, for a Vec<T> state field
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct HtmlMultiSelect<T: PartialEq + Clone + Debug + ToString + Serialize> {
+    pub id: String,
+    pub labeltext: String,
+    pub items: Vec<HtmlSelectItem<T>>,
+    pub selected_values: Vec<T>,
+    pub size: Option<u32>,
+    pub highlight: bool,
+    pub conflict: bool,
+    pub error: String,
+    pub hidden: bool,
+    pub disabled: bool,
+}
+
+impl<T> HtmlInput for HtmlMultiSelect<T>
+where
+    T: PartialEq + Clone + Debug + ToString + Serialize,
+{
+    fn template_data(&self, data: &mut RspTemplateMap) {
+        insert_template_value(data, self.get_sid(&self.id), &self);
+    }
+}
+
+impl<T> HtmlMultiSelect<T>
+where
+    T: std::cmp::PartialEq + std::clone::Clone + Debug + ToString + Serialize,
+{
+    pub fn item(self: &mut HtmlMultiSelect<T>, user_label: &str, value: T) {
+        push_item(&mut self.items, user_label, value);
+    }
+
+    /// Unlike with the select, no selection is a valid one
+    pub fn set_selected_values(self: &mut HtmlMultiSelect<T>, selected_values: &Vec<T>) {
+        select_many(&mut self.items, selected_values);
+        self.selected_values = selected_values.clone();
+    }
+}
+
+impl HtmlMultiSelect<String> {
+    pub fn item1(self: &mut HtmlMultiSelect<String>, user_label: &str) {
+        self.item(user_label, user_label.into());
     }
 }
@@ -20,21 +20,147 @@ pub trait HttpRequest {
             .and_then(|map| map.get(name).and_then(|v| v.first().cloned()))
     }
 
+    /// Get the raw session value stored under the key
+    fn get_session_raw(&mut self, key: &str) -> Option<String>;
+
+    /// Store the raw session value under the key
+    fn set_session_raw(&mut self, key: &str, value: String);
+
+    /// Remove all the session values of the client
+    fn clear_session(&mut self);
+
     /// Get session data by type
-    fn get_session<T: 'static>(&mut self) -> Option<&T>;
+    fn get_session<T: RspSessionValue>(&mut self) -> Option<T> {
+        self.get_session_raw(T::session_key())
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+    }
 
     /// Set session data
-    fn set_session<T: 'static>(&mut self, value: T);
+    fn set_session<T: RspSessionValue>(&mut self, value: &T) {
+        match serde_json::to_string(value) {
+            Ok(raw) => self.set_session_raw(T::session_key(), raw),
+            Err(e) => warn!("Can not serialize session value {}: {}", T::session_key(), e),
+        }
+    }
+
+    /// Get the framework-neutral description of the request
+    fn context(&mut self) -> RspRequestContext;
 
     /// Get global state by type
     fn get_state<T: 'static>(&self) -> Option<&T>;
 }
 
+/// What the page can know about the request, independent of the HTTP framework
+#[derive(Debug, Clone, Default)]
+pub struct RspRequestContext {
+    /// HTTP method, e.g. "GET"
+    pub method: String,
+    /// Request path, without the query string
+    pub path: String,
+    /// Query string, without the '?'
+    pub query: String,
+    /// Header values by lowercase header name
+    pub headers: HashMap<String, Vec<String>>,
+    /// Cookies sent by the client
+    pub cookies: HashMap<String, String>,
+    /// Address of the client, if the server knows it
+    pub remote_addr: Option<std::net::SocketAddr>,
+}
+
+impl RspRequestContext {
+    /// Add a header value, filling in the cookies if it is the Cookie header
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        let name = name.to_ascii_lowercase();
+        if name == "cookie" {
+            self.cookies.extend(parse_cookie_header(value));
+        }
+        self.headers.entry(name).or_default().push(value.to_string());
+    }
+
+    /// First value of the header, the name is case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .and_then(|values| values.first())
+            .map(|v| v.as_str())
+    }
+
+    /// Value of the cookie
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies.get(name).map(|v| v.as_str())
+    }
+}
+
+/// A value that can be kept in the per-client session
+pub trait RspSessionValue: serde::Serialize + serde::de::DeserializeOwned + 'static {
+    /// Key under which the value is stored in the session
+    fn session_key() -> &'static str;
+}
+
+/// Parse the value of a Cookie header into name/value pairs, undoing the
+/// percent-encoding of the values done by `RspCookie`
+pub fn parse_cookie_header(header: &str) -> HashMap<String, String> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), percent_decode(value.trim())))
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Percent-encode the bytes for which `keep` is false, and the '%' itself
+fn percent_encode(value: &str, keep: impl Fn(u8) -> bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte != b'%' && keep(byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// A cookie name has to be an HTTP token (RFC 6265)
+fn is_cookie_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&b))
+}
+
+/// The cookie-octet of RFC 6265: no controls, whitespace, '"', ',', ';' or '\'
+fn is_cookie_octet(byte: u8) -> bool {
+    byte.is_ascii_graphic() && !b"\",;\\".contains(&byte)
+}
+
 /// Abstract HTTP response builder
 pub trait HttpResponse {
     /// Create a new response with HTML content
     fn html(content: String) -> Self;
 
+    /// Create a response with a body of the given content type
+    fn bytes(content_type: &str, body: Vec<u8>) -> Self;
+
     /// Create a redirect response
     fn redirect(location: &str) -> Self;
 
@@ -43,6 +169,131 @@ pub trait HttpResponse {
 
     /// Set a header on the response
     fn set_header(&mut self, name: &str, value: &str);
+
+    /// Set the status code of the response
+    fn set_status(&mut self, status: u16);
+}
+
+/// Headers a page adds to its response
+///
+/// Set-Cookie headers are all sent, for the other headers the last value
+/// replaces the earlier ones, including the ones set by the framework.
+#[derive(Debug, Clone, Default)]
+pub struct RspResponseHeaders {
+    headers: Vec<(String, String)>,
+}
+
+impl RspResponseHeaders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a header, e.g. Cache-Control or Content-Disposition
+    pub fn add(&mut self, name: &str, value: &str) {
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    /// Add a Set-Cookie header
+    pub fn set_cookie(&mut self, cookie: &RspCookie) {
+        self.add("Set-Cookie", &cookie.to_string());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, String)> {
+        self.headers.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+}
+
+/// A cookie to send to the client
+#[derive(Debug, Clone)]
+pub struct RspCookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    max_age: Option<i64>,
+    http_only: bool,
+    secure: bool,
+    same_site: Option<String>,
+}
+
+impl RspCookie {
+    /// The value is percent-encoded where it is not a valid cookie value,
+    /// `parse_cookie_header` decodes it again.
+    ///
+    /// Panics if the name is not a valid cookie name - the names are chosen by the code.
+    pub fn new(name: &str, value: &str) -> Self {
+        assert!(is_cookie_name(name), "invalid cookie name {:?}", name);
+        RspCookie {
+            name: name.to_string(),
+            value: percent_encode(value, is_cookie_octet),
+            path: Some("/".to_string()),
+            max_age: None,
+            http_only: false,
+            secure: false,
+            same_site: None,
+        }
+    }
+
+    /// A cookie which makes the client forget the cookie of this name
+    pub fn removal(name: &str) -> Self {
+        RspCookie::new(name, "").max_age(0)
+    }
+
+    /// The path is percent-encoded where it would end the attribute
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(percent_encode(path, |b| b.is_ascii_graphic() && b != b';'));
+        self
+    }
+
+    /// Lifetime in seconds - without it the cookie lasts until the browser is closed
+    pub fn max_age(mut self, seconds: i64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// "Strict", "Lax" or "None" - anything else is left out, with a warning
+    pub fn same_site(mut self, same_site: &str) -> Self {
+        match ["Strict", "Lax", "None"].iter().find(|v| v.eq_ignore_ascii_case(same_site)) {
+            Some(same_site) => self.same_site = Some(same_site.to_string()),
+            None => warn!("Ignoring invalid SameSite {:?} of cookie {}", same_site, self.name),
+        }
+        self
+    }
+}
+
+impl std::fmt::Display for RspCookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(ref path) = self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age)?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if let Some(ref same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
 }
 
 /// Result type for HTTP handlers
@@ -53,20 +304,106 @@ pub type HttpResult<R> = Result<R, HttpError>;
 pub enum HttpError {
     BadRequest(String),
     Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
     InternalError(String),
 }
 
-impl std::fmt::Display for HttpError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl HttpError {
+    /// HTTP status code corresponding to the error
+    pub fn status_code(&self) -> u16 {
+        match self {
+            HttpError::BadRequest(_) => 400,
+            HttpError::Unauthorized(_) => 401,
+            HttpError::Forbidden(_) => 403,
+            HttpError::NotFound(_) => 404,
+            HttpError::Conflict(_) => 409,
+            HttpError::InternalError(_) => 500,
+        }
+    }
+
+    /// Short name of the error kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HttpError::BadRequest(_) => "Bad Request",
+            HttpError::Unauthorized(_) => "Unauthorized",
+            HttpError::Forbidden(_) => "Forbidden",
+            HttpError::NotFound(_) => "Not Found",
+            HttpError::Conflict(_) => "Conflict",
+            HttpError::InternalError(_) => "Internal Error",
+        }
+    }
+
+    /// The message carried by the error
+    pub fn message(&self) -> &str {
+        match self {
+            HttpError::BadRequest(msg)
+            | HttpError::Unauthorized(msg)
+            | HttpError::Forbidden(msg)
+            | HttpError::NotFound(msg)
+            | HttpError::Conflict(msg)
+            | HttpError::InternalError(msg) => msg,
+        }
+    }
+
+    /// The message that can be shown to the client - the details of an internal
+    /// error are only for the log
+    pub fn public_message(&self) -> &str {
         match self {
-            HttpError::BadRequest(msg) => write!(f, "Bad Request: {}", msg),
-            HttpError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
-            HttpError::InternalError(msg) => write!(f, "Internal Error: {}", msg),
+            HttpError::InternalError(_) => "The server could not handle the request",
+            _ => self.message(),
         }
     }
 }
 
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind(), self.message())
+    }
+}
+
 impl std::error::Error for HttpError {}
 
+static STOP_REQUESTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Ask the server to stop, e.g. from an event handler - the Axum server shuts down
+/// gracefully, with Iron the loop around `RspServer::run` sees `Rsp10GlobalData::stop_requested`
+pub fn request_server_stop() {
+    STOP_REQUESTED.store(true, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Whether `request_server_stop` was called
+pub fn server_stop_requested() -> bool {
+    STOP_REQUESTED.load(std::sync::atomic::Ordering::SeqCst)
+}
+
 // Note: HttpAdapter trait removed - we use concrete types instead
 // Each framework adapter provides its own concrete request wrapper
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cookie_value_can_not_add_attributes() {
+        let cookie = RspCookie::new("name", "a; Domain=evil\r\nX: y%").to_string();
+        assert_eq!(cookie, "name=a%3B%20Domain=evil%0D%0AX:%20y%25; Path=/");
+        let value = cookie.split("; ").next().unwrap();
+        assert_eq!(parse_cookie_header(value)["name"], "a; Domain=evil\r\nX: y%");
+    }
+
+    #[test]
+    fn cookie_path_and_same_site_are_checked() {
+        let cookie = RspCookie::new("name", "v").path("/a;b\r\n").same_site("lax\r\n").to_string();
+        assert_eq!(cookie, "name=v; Path=/a%3Bb%0D%0A");
+        let cookie = RspCookie::new("name", "v").same_site("strict").to_string();
+        assert_eq!(cookie, "name=v; Path=/; SameSite=Strict");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid cookie name")]
+    fn cookie_name_must_be_a_token() {
+        RspCookie::new("a=b; c", "v");
+    }
+}
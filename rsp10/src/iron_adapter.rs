@@ -9,10 +9,26 @@ use iron_sessionstorage::{SessionStorage, Value, SessionRequestExt};
 use persistent::State;
 use urlencoded::{UrlEncodedBody, UrlEncodedQuery};
 
-use crate::http_adapter::{HttpRequest, HttpResponse, HttpResult, HttpError};
-use crate::core::{RspState, RspUserAuth, RspEvent, RspAction, extract_event, extract_json_state, amend_json_value};
+use crate::http_adapter::{HttpRequest, HttpResponse, HttpResult, HttpError, RspSessionValue, RspRequestContext};
+use crate::core::{RspState, RspUserAuth, PageEngine};
 use crate::core::RspKey;
-use crate::{Rsp10GlobalData, maybe_compile_template};
+use crate::Rsp10GlobalData;
+
+/// All the rsp10 session values of a client, kept in one signed cookie
+#[derive(Default, Serialize, Deserialize)]
+struct IronSessionMap(HashMap<String, String>);
+
+impl Value for IronSessionMap {
+    fn get_key() -> &'static str {
+        "rsp10_session"
+    }
+    fn into_raw(self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+    fn from_raw(value: String) -> Option<Self> {
+        serde_json::from_str(&value).ok()
+    }
+}
 
 /// Wrapper to implement HttpRequest for Iron's Request
 pub struct IronRequestAdapter<'req, 'a, 'b> {
@@ -50,15 +66,37 @@ impl<'req, 'a, 'b> HttpRequest for IronRequestAdapter<'req, 'a, 'b> {
         }
     }
 
-    fn get_session<T: 'static>(&mut self) -> Option<&T> {
-        // Generic session access doesn't work due to Key trait requirements
-        // For Iron-specific code, use extensions directly or get_iron_session()
-        None
+    fn get_session_raw(&mut self, key: &str) -> Option<String> {
+        let map = self.req.session().get::<IronSessionMap>().ok().and_then(|x| x);
+        map.and_then(|mut m| m.0.remove(key))
+    }
+
+    fn set_session_raw(&mut self, key: &str, value: String) {
+        let mut map = self.req.session().get::<IronSessionMap>().ok().and_then(|x| x).unwrap_or_default();
+        map.0.insert(key.to_string(), value);
+        if let Err(e) = self.req.session().set(map) {
+            warn!("Can not store the session: {:?}", e);
+        }
+    }
+
+    fn clear_session(&mut self) {
+        if let Err(e) = self.req.session().clear() {
+            warn!("Can not clear the session: {:?}", e);
+        }
     }
 
-    fn set_session<T: 'static>(&mut self, _value: T) {
-        // Generic session access doesn't work due to Key trait requirements
-        // For Iron-specific code, use extensions directly or set_iron_session()
+    fn context(&mut self) -> RspRequestContext {
+        let mut ctx = RspRequestContext {
+            method: self.req.method.to_string(),
+            path: format!("/{}", self.req.url.path().join("/")),
+            query: self.req.url.query().unwrap_or_default().to_string(),
+            remote_addr: Some(self.req.remote_addr),
+            ..Default::default()
+        };
+        for header in self.req.headers.iter() {
+            ctx.add_header(header.name(), &header.value_string());
+        }
+        ctx
     }
 
     fn get_state<T: 'static>(&self) -> Option<&T> {
@@ -68,7 +106,7 @@ impl<'req, 'a, 'b> HttpRequest for IronRequestAdapter<'req, 'a, 'b> {
 
 /// Iron response builder
 pub struct IronResponseBuilder {
-    content: String,
+    content: Vec<u8>,
     status: status::Status,
     headers: Vec<(String, String)>,
 }
@@ -76,7 +114,7 @@ pub struct IronResponseBuilder {
 impl HttpResponse for IronResponseBuilder {
     fn html(content: String) -> Self {
         IronResponseBuilder {
-            content,
+            content: content.into_bytes(),
             status: status::Ok,
             headers: vec![
                 ("Content-Type".to_string(), "text/html; charset=utf-8".to_string()),
@@ -85,9 +123,17 @@ impl HttpResponse for IronResponseBuilder {
         }
     }
 
+    fn bytes(content_type: &str, body: Vec<u8>) -> Self {
+        IronResponseBuilder {
+            content: body,
+            status: status::Ok,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+        }
+    }
+
     fn redirect(location: &str) -> Self {
         IronResponseBuilder {
-            content: location.to_string(),
+            content: location.as_bytes().to_vec(),
             status: status::Found,
             headers: vec![
                 ("Content-Type".to_string(), "text/html; charset=utf-8".to_string()),
@@ -97,15 +143,9 @@ impl HttpResponse for IronResponseBuilder {
     }
 
     fn error(status_code: u16, message: String) -> Self {
-        let status = match status_code {
-            400 => status::BadRequest,
-            401 => status::Unauthorized,
-            404 => status::NotFound,
-            500 => status::InternalServerError,
-            _ => status::InternalServerError,
-        };
+        let status = status::Status::from_u16(status_code);
         IronResponseBuilder {
-            content: message,
+            content: message.into_bytes(),
             status,
             headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
         }
@@ -114,29 +154,21 @@ impl HttpResponse for IronResponseBuilder {
     fn set_header(&mut self, name: &str, value: &str) {
         self.headers.push((name.to_string(), value.to_string()));
     }
+
+    fn set_status(&mut self, status_code: u16) {
+        self.status = status::Status::from_u16(status_code);
+    }
 }
 
 impl IronResponseBuilder {
     pub fn into_iron_response(self) -> Response {
-        use iron::headers::{ContentType, Connection, Location};
-        use iron::modifiers::Header;
+        let mut resp = Response::with((self.status, self.content));
 
-        let mut resp = Response::with((self.status, self.content.clone()));
-
-        for (name, value) in &self.headers {
-            match name.as_str() {
-                "Content-Type" if value.contains("text/html") => {
-                    resp.headers.set(ContentType::html());
-                }
-                "Connection" if value == "close" => {
-                    resp.headers.set(Connection::close());
-                }
-                "Location" => {
-                    resp.headers.set(Location(value.clone()));
-                }
-                _ => {
-                    // Generic header setting
-                }
+        for (name, value) in self.headers {
+            if name.eq_ignore_ascii_case("Set-Cookie") {
+                resp.headers.append_raw(name, value.into_bytes());
+            } else {
+                resp.headers.set_raw(name, vec![value.into_bytes()]);
             }
         }
 
@@ -152,7 +184,7 @@ pub struct RspIronHandler<S, T, TA>
 where
     S: RspState<T, TA> + Send + Sync + 'static,
     T: serde::Serialize + std::fmt::Debug + Clone + Default + serde::de::DeserializeOwned + Send + Sync + 'static + RspKey,
-    TA: RspUserAuth + serde::Serialize + Send + Sync + Value + Clone + iron::typemap::Key<Value = TA> + 'static,
+    TA: RspUserAuth + RspSessionValue + Send + Sync + 'static,
 {
     _phantom: std::marker::PhantomData<(S, T, TA)>,
 }
@@ -161,7 +193,7 @@ impl<S, T, TA> RspIronHandler<S, T, TA>
 where
     S: RspState<T, TA> + Send + Sync + 'static,
     T: serde::Serialize + std::fmt::Debug + Clone + Default + serde::de::DeserializeOwned + Send + Sync + 'static + RspKey,
-    TA: RspUserAuth + serde::Serialize + Send + Sync + Value + Clone + iron::typemap::Key<Value = TA> + 'static,
+    TA: RspUserAuth + RspSessionValue + Send + Sync + 'static,
 {
     pub fn new() -> Self {
         RspIronHandler {
@@ -174,185 +206,14 @@ impl<S, T, TA> Handler for RspIronHandler<S, T, TA>
 where
     S: RspState<T, TA> + Send + Sync + 'static,
     T: serde::Serialize + std::fmt::Debug + Clone + Default + serde::de::DeserializeOwned + Send + Sync + 'static + RspKey,
-    TA: RspUserAuth + serde::Serialize + Send + Sync + Value + Clone + iron::typemap::Key<Value = TA> + 'static,
+    TA: RspUserAuth + RspSessionValue + Send + Sync + 'static,
 {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        // Load authenticated user from session
-        let session_auth: Option<TA> = req.session().get::<TA>().ok().and_then(|x| x);
-
         let mut adapter = IronRequestAdapter::new(req);
+        let outcome = PageEngine::<S, T, TA>::run(&mut adapter);
 
-        let auth = if let Some(auth_from_session) = session_auth {
-            // User is authenticated via session
-            auth_from_session
-        } else {
-            // No session - call from_request to authenticate
-            let auth_res = TA::from_request(&mut adapter);
-
-            match auth_res {
-                Ok(a) => a,
-                Err(login_url) => {
-                    let resp = IronResponseBuilder::redirect(&login_url);
-                    return Ok(resp.into_iron_response());
-                }
-            }
-        };
-
-        // Get form data
-        let form_data = adapter.form_data().unwrap_or_default();
-        let query_params = adapter.query_params().unwrap_or_default();
-
-        // Extract event
-        let event = extract_event(&form_data);
-
-        // Get or reconstruct state
-        let mut maybe_state_val: Option<serde_json::Value> =
-            extract_json_state(&form_data, "state_json");
-
-        let mut maybe_state = if let Some(ref mut state_val) = maybe_state_val {
-            amend_json_value(state_val, &form_data);
-            serde_json::from_value(state_val.clone()).ok()
-        } else {
-            None
-        };
-
-        let maybe_initial_state: Option<S> =
-            extract_json_state(&form_data, "initial_state_json");
-
-        // Get key
-        let mut maybe_key = S::get_key(&auth, &query_params, &maybe_state);
-        if maybe_key.is_none() {
-            maybe_key = S::get_key_from_args(&auth, &query_params);
-        }
-        let mut key = maybe_key.unwrap_or_default();
-
-        // Get current initial state
-        let mut curr_initial_state = S::get_state(&auth, key.clone());
-        let state_none = maybe_state.is_none();
-        let initial_state_none = maybe_initial_state.is_none();
-        let initial_state = maybe_initial_state.unwrap_or(curr_initial_state.clone());
-        let state = maybe_state.unwrap_or(initial_state.clone());
-
-        // Handle event
-        let ri = crate::core::RspInfo {
-            auth: &auth,
-            event: &event,
-            key: &key,
-            state,
-            state_none,
-            initial_state,
-            initial_state_none,
-            curr_initial_state: &curr_initial_state,
-        };
-
-        let r = S::event_handler(ri);
-        let mut initial_state = r.initial_state;
-        let mut state = r.state;
-        let action = r.action;
-        let new_auth = r.new_auth;
-
-        // Process action
-        let mut redirect_to = String::new();
-        match action {
-            RspAction::Render => {}
-            RspAction::ReloadState => {
-                curr_initial_state = S::get_state(&auth, key.clone());
-                initial_state = curr_initial_state.clone();
-                state = curr_initial_state.clone();
-            }
-            RspAction::RedirectTo(target) => {
-                redirect_to = target;
-            }
-            RspAction::SetKey(k) => {
-                key = k;
-                curr_initial_state = S::get_state(&auth, key.clone());
-                initial_state = curr_initial_state.clone();
-                state = curr_initial_state.clone();
-            }
-        }
-
-        // Save session if a new auth was provided
-        if let Some(new_auth_any) = new_auth {
-            // Try to downcast to TA first (same type as page auth)
-            if let Some(new_auth_typed) = new_auth_any.downcast_ref::<TA>() {
-                // Store in session - will be persisted as signed cookie
-                let _ = req.session().set(new_auth_typed.clone());
-                // Also store in extensions for immediate access
-                req.extensions.insert::<TA>(new_auth_typed.clone());
-            } else {
-                // If downcast to TA fails, try concrete auth types
-                // This allows login pages (with NoPageAuth) to return CookiePageAuth
-                if let Some(new_auth_cookie) = new_auth_any.downcast_ref::<crate::CookiePageAuth>() {
-                    let _ = req.session().set(new_auth_cookie.clone());
-                }
-            }
-        }
-
-        if !redirect_to.is_empty() {
-            let resp = IronResponseBuilder::redirect(&redirect_to);
-            return Ok(resp.into_iron_response());
-        }
-
-        // Render template
-        let template_name = if S::get_template_name() != "" {
-            S::get_template_name()
-        } else {
-            S::get_template_name_auto()
-        };
-
-        let template = match maybe_compile_template(&template_name) {
-            Ok(t) => t,
-            Err(e) => {
-                let resp = IronResponseBuilder::error(500, format!("Template error: {}", e));
-                return Ok(resp.into_iron_response());
-            }
-        };
-
-        // Fill data
-        let ri = crate::core::RspInfo {
-            auth: &auth,
-            event: &event,
-            key: &key,
-            state,
-            state_none: false,
-            initial_state,
-            initial_state_none: false,
-            curr_initial_state: &curr_initial_state,
-        };
-
-        let r = S::fill_data(ri);
-        let initial_state = r.initial_state;
-        let state = r.state;
-        let data = r.data;
-
-        // Build template data
-        let data = data.insert("auth", &auth).unwrap();
-        let data = data.insert("state", &state).unwrap();
-        let data = data.insert("state_key", &key).unwrap();
-        let data = data.insert("initial_state", &initial_state).unwrap();
-        let data = data.insert("curr_initial_state", &curr_initial_state).unwrap();
-        let data = data
-            .insert("state_json", &serde_json::to_string(&state).unwrap())
-            .unwrap();
-        let data = data
-            .insert("state_key_json", &serde_json::to_string(&key).unwrap())
-            .unwrap();
-        let data = data
-            .insert("initial_state_json", &serde_json::to_string(&initial_state).unwrap())
-            .unwrap();
-        let data = data
-            .insert("curr_initial_state_json", &serde_json::to_string(&curr_initial_state).unwrap())
-            .unwrap();
-
-        // Render
-        let mut bytes = vec![];
-        let data_built = data.build();
-        template
-            .render_data(&mut bytes, &data_built)
-            .expect("Failed to render");
-        let payload = std::str::from_utf8(&bytes).unwrap();
-
-        let resp = IronResponseBuilder::html(payload.to_string());
+        // Session changes are persisted as a signed cookie by the SessionStorage middleware
+        let resp: IronResponseBuilder = outcome.into_response();
         Ok(resp.into_iron_response())
     }
 }
@@ -362,7 +223,7 @@ pub fn make_iron_handler<S, T, TA>() -> RspIronHandler<S, T, TA>
 where
     S: RspState<T, TA> + Send + Sync + 'static,
     T: serde::Serialize + std::fmt::Debug + Clone + Default + serde::de::DeserializeOwned + Send + Sync + 'static + RspKey,
-    TA: RspUserAuth + serde::Serialize + Send + Sync + Value + Clone + iron::typemap::Key<Value = TA> + 'static,
+    TA: RspUserAuth + RspSessionValue + Send + Sync + 'static,
 {
     RspIronHandler::new()
 }
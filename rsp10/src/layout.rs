@@ -0,0 +1,182 @@
+/// Layouts and the hidden state fields of the forms
+///
+/// A page can be rendered into a layout - a base template with the common
+/// `<html><head><body>` skeleton. The layout is chosen per page with
+/// `#[rsp_layout("name")]`, or for all the pages with `set_default_layout`,
+/// and the rendered page goes into its `{{{content}}}` slot (or the one given
+/// with `#[rsp_layout("name", slot = "main")]`).
+///
+/// The post forms of the rendered page get the `state_json` and `initial_state_json`
+/// hidden fields added, unless they have them already - without them the state
+/// does not come back with the post. Forms posting to another site are left alone.
+
+use std::sync::RwLock;
+
+static DEFAULT_LAYOUT: RwLock<Option<String>> = RwLock::new(None);
+
+/// Set the layout of the pages that do not choose their own, "" for none
+pub fn set_default_layout(name: &str) {
+    if let Ok(mut lock) = DEFAULT_LAYOUT.write() {
+        *lock = Some(name.to_string());
+    }
+}
+
+/// The layout of the pages that do not choose their own, "" if none
+pub fn default_layout() -> String {
+    DEFAULT_LAYOUT
+        .read()
+        .ok()
+        .and_then(|lock| lock.clone())
+        .unwrap_or_default()
+}
+
+pub fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn has_field(form_html: &str, name: &str) -> bool {
+    form_html.contains(&format!("name=\"{}\"", name)) || form_html.contains(&format!("name='{}'", name))
+}
+
+/// Whether the opening tag has `method="post"`, in any case and spacing around the `=`
+fn is_post_form(open_tag: &str) -> bool {
+    let tag = open_tag.to_ascii_lowercase().replace(['"', '\''], "");
+    let tag = tag.split('=').map(str::trim).collect::<Vec<_>>().join("=");
+    tag.split(|c: char| c.is_ascii_whitespace() || c == '>').any(|attr| attr == "method=post")
+}
+
+/// The value of the `action` attribute of the opening tag, if it has one
+fn form_action(open_tag: &str) -> Option<&str> {
+    let lower = open_tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(found) = lower[from..].find("action") {
+        let start = from + found;
+        from = start + "action".len();
+        if !lower[..start].ends_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let rest = open_tag[from..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+        return Some(match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value.split(|c: char| c.is_ascii_whitespace() || c == '>').next().unwrap_or_default(),
+        });
+    }
+    None
+}
+
+/// Whether the form posts back to this site: no action, or a relative one -
+/// the state must not be sent to another origin
+fn posts_to_this_site(open_tag: &str) -> bool {
+    let action = match form_action(open_tag) {
+        Some(action) => action.trim().replace('\\', "/"),
+        None => return true,
+    };
+    if action.starts_with("//") {
+        return false;
+    }
+    // A scheme, e.g. "https:", comes before any '/', '?' or '#'
+    let scheme_end = action.find(':');
+    let path_start = action.find(['/', '?', '#']);
+    match (scheme_end, path_start) {
+        (Some(colon), Some(path)) => colon > path,
+        (Some(_), None) => false,
+        (None, _) => true,
+    }
+}
+
+/// Add the hidden fields, as `(name, value)`, to the post forms that do not have them
+/// and post back to this site
+pub fn inject_hidden_fields(html: &str, fields: &[(&str, &str)]) -> String {
+    // ASCII lowercasing keeps the offsets the same
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while let Some(found) = lower[pos..].find("<form") {
+        let start = pos + found;
+        let open_end = match lower[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        let is_form_tag = lower[start + 5..].starts_with(|c: char| c.is_ascii_whitespace() || c == '>');
+        out.push_str(&html[pos..open_end]);
+        pos = open_end;
+        if !is_form_tag || !is_post_form(&lower[start..open_end]) || !posts_to_this_site(&html[start..open_end]) {
+            continue;
+        }
+        let close = lower[open_end..].find("</form").map(|c| open_end + c).unwrap_or(lower.len());
+        let form_html = &lower[open_end..close];
+        for (name, value) in fields {
+            if !has_field(form_html, name) {
+                out.push_str(&format!(
+                    "\n<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                    name,
+                    html_escape(value)
+                ));
+            }
+        }
+    }
+    out.push_str(&html[pos..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_forms_are_found() {
+        assert!(is_post_form("<form method=\"post\">"));
+        assert!(is_post_form("<form method = \"post\">"));
+        assert!(is_post_form("<FORM Method='POST' action=\"/x\">"));
+        assert!(!is_post_form("<form method=\"get\">"));
+        assert!(!is_post_form("<form data-method=\"post\">"));
+    }
+
+    #[test]
+    fn hidden_fields_go_into_post_forms() {
+        let html = inject_hidden_fields("<form method = 'POST'></form><form></form>", &[("state_json", "{}")]);
+        assert_eq!(html.matches("name=\"state_json\"").count(), 1);
+        assert!(html.starts_with("<form method = 'POST'>\n<input type=\"hidden\""));
+    }
+
+    #[test]
+    fn only_forms_posting_to_this_site_get_the_fields() {
+        for tag in [
+            "<form method=\"post\">",
+            "<form method=\"post\" action=\"\">",
+            "<form method=\"post\" action=\"/orders?id=1\">",
+            "<form method=\"post\" action='edit'>",
+            "<form method=\"post\" action=?page=2>",
+            "<form method=\"post\" data-action=\"https://example.com/\">",
+        ] {
+            let html = inject_hidden_fields(&format!("{}</form>", tag), &[("state_json", "{}")]);
+            assert!(html.contains("name=\"state_json\""), "{}", tag);
+        }
+        for tag in [
+            "<form method=\"post\" action=\"https://example.com/collect\">",
+            "<form method=\"post\" ACTION = 'HTTP://example.com'>",
+            "<form method=\"post\" action=\"//example.com/collect\">",
+            "<form method=\"post\" action=\"/\\example.com/collect\">",
+            "<form method=\"post\" action=mailto:someone@example.com>",
+        ] {
+            let html = inject_hidden_fields(&format!("{}</form>", tag), &[("state_json", "{}")]);
+            assert!(!html.contains("state_json"), "{}", tag);
+        }
+    }
+}
@@ -23,6 +23,27 @@ use crate::core::RspKey;
 // New modular architecture - framework agnostic core
 pub mod http_adapter;
 pub mod core;
+pub mod state_codec;
+pub use state_codec::RspStateEncoding;
+pub mod conflict;
+pub use conflict::{RspConflictPolicy, RspFieldConflict};
+mod prg;
+pub mod validation;
+pub use validation::{RspFieldError, RspValidator};
+pub mod grid;
+pub use grid::{RspRow, RspRowData, RspRowInfo};
+pub mod template_check;
+pub mod template_cache;
+pub mod resources;
+pub mod layout;
+pub use layout::set_default_layout;
+pub mod engine;
+pub use engine::{set_template_engine, RspTemplateData, RspTemplateEngine, RspTemplateError, MustacheEngine};
+#[cfg(feature = "minijinja")]
+pub use engine::MiniJinjaEngine;
+pub use resources::{set_embedded_templates, set_static_root, set_template_root, static_root, template_root};
+pub use template_cache::{cached_template, preload_templates, set_template_reload};
+pub use template_check::{check_page_template, RspTemplateProblem};
 
 // Framework-specific adapters
 #[cfg(feature = "iron")]
@@ -41,11 +62,15 @@ pub type RspDataBuilder = foobuilder::FooMapBuilder;
 
 // Re-export derive macro
 pub use rsp10_derive::RspState as DeriveRspState;
+pub use rsp10_derive::RspRow as DeriveRspRow;
+pub use rsp10_derive::embed_templates;
 
 // Re-export core types for public API
 pub use core::{
-    RspEvent, RspAction, RspInfo, RspEventHandlerResult, RspFillDataResult,
-    RspUserAuth, RspState, extract_event, extract_json_state, amend_json_value,
+    RspEvent, RspAction, RspInfo, RspEventHandlerResult, RspFillDataResult, RspNewAuth,
+    RspUserAuth, RspState, RspStateMeta, extract_event, extract_json_state, encode_json_state, amend_json_value,
+    amend_json_state, is_state_input,
+    PageEngine, RspPageOutcome, RspPageResponse,
 };
 
 // Common auth types
@@ -53,7 +78,10 @@ pub mod common_auth;
 pub use common_auth::{NoPageAuth, CookiePageAuth};
 
 // Re-export HTTP abstraction
-pub use http_adapter::{HttpRequest, HttpResponse, HttpResult, HttpError};
+pub use http_adapter::{
+    HttpRequest, HttpResponse, HttpResult, HttpError, RspSessionValue, RspRequestContext,
+    RspResponseHeaders, RspCookie, request_server_stop, server_stop_requested,
+};
 
 // Re-export Iron adapter if feature is enabled
 #[cfg(feature = "iron")]
@@ -71,12 +99,22 @@ impl<S, T, TA> WebHandler<S, T, TA> {
         }
     }
 
+    /// Check the template of the page, see `template_check`
+    pub fn check_template(&self) -> Vec<RspTemplateProblem>
+    where
+        S: RspState<T, TA>,
+        T: RspKey,
+        TA: RspUserAuth + serde::Serialize,
+    {
+        check_page_template::<S, T, TA>()
+    }
+
     #[cfg(feature = "iron")]
     pub fn to_iron(self) -> iron_adapter::RspIronHandler<S, T, TA>
     where
         S: RspState<T, TA> + Send + Sync + 'static,
         T: RspKey + serde::Serialize + std::fmt::Debug + Clone + Default + serde::de::DeserializeOwned + Send + Sync + 'static,
-        TA: RspUserAuth + serde::Serialize + Send + Sync + iron_sessionstorage::Value + Clone + iron::typemap::Key<Value = TA> + 'static,
+        TA: RspUserAuth + RspSessionValue + Send + Sync + 'static,
     {
         make_iron_handler::<S, T, TA>()
     }
@@ -85,25 +123,35 @@ impl<S, T, TA> WebHandler<S, T, TA> {
     pub fn to_axum(
         self
     ) -> impl Fn(
-        axum::extract::State<std::sync::Arc<tokio::sync::Mutex<axum_adapter::SessionData>>>,
-        axum::extract::Query<std::collections::HashMap<String, String>>,
-        Option<axum::extract::Form<std::collections::HashMap<String, String>>>,
+        axum::extract::State<axum_adapter::SessionStore>,
+        axum::http::request::Parts,
+        axum::extract::Query<Vec<(String, String)>>,
+        Option<axum::extract::Form<Vec<(String, String)>>>,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = axum::response::Response> + Send>> + Clone
     where
         S: RspState<T, TA> + 'static,
         T: RspKey + 'static,
-        TA: RspUserAuth + serde::Serialize + serde::de::DeserializeOwned + Default + 'static,
+        TA: RspUserAuth + RspSessionValue,
     {
-        move |state, query, form| {
-            Box::pin(axum_adapter::axum_handler_fn::<S, T, TA>((query, form, state)))
-        }
+        axum_adapter::make_axum_handler::<S, T, TA>()
     }
 }
 
 
 // Template utilities
+pub fn template_path(name: &str) -> String {
+    format!("{}/{}.{}", template_root(), name, engine::template_engine().extension())
+}
+
 pub fn maybe_compile_template(name: &str) -> Result<Template, mustache::Error> {
-    let fname = format!("./templates/{}.mustache", name);
+    if resources::has_embedded_templates() {
+        debug!("Compiling embedded template: {}", name);
+        return match resources::embedded_template(name) {
+            Some(source) => mustache::compile_str(&resources::inline_partials(source, 0)),
+            None => Err(mustache::Error::Io(resources::not_embedded(name))),
+        };
+    }
+    let fname = format!("{}/{}.mustache", template_root(), name);
     debug!("Compiling template: {}", &fname);
     mustache::compile_path(fname)
 }
@@ -115,7 +163,7 @@ pub fn compile_template(name: &str) -> Template {
 #[macro_export]
 macro_rules! get_page_template {
     ( $name: expr) => {
-        match $crate::maybe_compile_template($name) {
+        match $crate::cached_template($name) {
             Ok(t) => t,
             Err(e) => {
                 return Err($crate::HttpError::InternalError(format!("Template error: {}", e)));
@@ -147,10 +195,19 @@ macro_rules! rsp_routes {
             r
         }
 
+        // Template checker for all the pages
+        pub fn check_templates() -> Vec<rsp10::RspTemplateProblem> {
+            let mut problems = vec![];
+            $(
+                problems.extend($module::web_handler().check_template());
+            )*
+            problems
+        }
+
         // Axum router function
         #[cfg(feature = "axum")]
         pub fn get_axum_router(
-            session_data: std::sync::Arc<tokio::sync::Mutex<rsp10::axum_adapter::SessionData>>
+            session_store: rsp10::axum_adapter::SessionStore
         ) -> axum::Router {
             use axum::routing::{get, post};
             use tower_http::services::ServeDir;
@@ -162,8 +219,8 @@ macro_rules! rsp_routes {
                         get(handler.clone()).post(handler)
                     })
                 )*
-                .nest_service("/static", ServeDir::new("staticfiles/"))
-                .with_state(session_data)
+                .nest_service("/static", ServeDir::new(rsp10::static_root()))
+                .with_state(session_store)
         }
     };
 }
@@ -212,7 +269,26 @@ macro_rules! rsp10_select {
             let mut $elt = $elt.borrow_mut();
             $elt.set_selected_value(&mut $rinfo.state.$elt);
             $elt.highlight = $rinfo.state.$elt != $rinfo.initial_state.$elt;
-            $elt.id = format!("{}", stringify!($elt));
+            $elt.conflict = $rinfo.has_conflict(stringify!($elt));
+            $elt.error = $rinfo.field_error(stringify!($elt)).unwrap_or_default().to_string();
+            $elt.id = $rinfo.element_id(stringify!($elt));
+            $modified = $modified || $elt.highlight;
+        }
+        rsp10_gd!($gd, $elt);
+    };
+}
+
+#[macro_export]
+macro_rules! rsp10_radio {
+    ( $elt: ident, $from: expr , $rinfo: ident => $gd: ident, $modified: ident) => {
+        let mut $elt = std::rc::Rc::new(std::cell::RefCell::new($from.clone()));
+        {
+            let mut $elt = $elt.borrow_mut();
+            $elt.set_selected_value(&mut $rinfo.state.$elt);
+            $elt.highlight = $rinfo.state.$elt != $rinfo.initial_state.$elt;
+            $elt.conflict = $rinfo.has_conflict(stringify!($elt));
+            $elt.error = $rinfo.field_error(stringify!($elt)).unwrap_or_default().to_string();
+            $elt.id = $rinfo.element_id(stringify!($elt));
             $modified = $modified || $elt.highlight;
         }
         rsp10_gd!($gd, $elt);
@@ -227,14 +303,130 @@ macro_rules! rsp10_text {
         {
             let mut $elt = $elt.borrow_mut();
             $elt.highlight = $rinfo.state.$elt != $rinfo.initial_state.$elt;
+            $elt.conflict = $rinfo.has_conflict(stringify!($elt));
+            $elt.error = $rinfo.field_error(stringify!($elt)).unwrap_or_default().to_string();
             $elt.value = $rinfo.state.$elt.clone().to_string();
-            $elt.id = format!("{}", stringify!($elt));
+            $elt.id = $rinfo.element_id(stringify!($elt));
             $modified = $modified || $elt.highlight;
         }
         rsp10_gd!($gd, $elt);
     };
 }
 
+#[macro_export]
+macro_rules! rsp10_multiselect {
+    ( $elt: ident, $from: expr , $rinfo: ident => $gd: ident, $modified: ident) => {
+        let mut $elt = std::rc::Rc::new(std::cell::RefCell::new($from.clone()));
+        {
+            let mut $elt = $elt.borrow_mut();
+            $elt.set_selected_values(&$rinfo.state.$elt);
+            $elt.highlight = $rinfo.state.$elt != $rinfo.initial_state.$elt;
+            $elt.conflict = $rinfo.has_conflict(stringify!($elt));
+            $elt.error = $rinfo.field_error(stringify!($elt)).unwrap_or_default().to_string();
+            $elt.id = $rinfo.element_id(stringify!($elt));
+            $modified = $modified || $elt.highlight;
+        }
+        rsp10_gd!($gd, $elt);
+    };
+}
+
+/// Rows of an editable grid, for a Vec<R: RspRow> field
+#[macro_export]
+macro_rules! rsp10_grid {
+    ( $elt: ident, $rinfo: ident => $gd: ident, $modified: ident) => {
+        {
+            let rows = $rinfo.state.$elt.clone();
+            let vb = $gd.vector_builder(stringify!($elt));
+            for (i, row) in rows.into_iter().enumerate() {
+                let initial = $rinfo.initial_state.$elt.get(i).cloned();
+                let mut row_info = $crate::RspRowInfo::new(
+                    stringify!($elt), i, row, initial, $rinfo.conflicts, $rinfo.errors,
+                );
+                let mut row_data = $crate::RspRowData::new(vb, stringify!($elt), i);
+                $modified = $crate::RspRow::fill_row(&mut row_info, &mut row_data) || $modified;
+                $rinfo.state.$elt[i] = row_info.state;
+            }
+            $modified = $modified || $rinfo.state.$elt.len() != $rinfo.initial_state.$elt.len();
+        }
+    };
+}
+
+/// The common part of the macros for the single-value inputs
+#[doc(hidden)]
+#[macro_export]
+macro_rules! rsp10_input {
+    ($ty: ty, $elt: ident, $rinfo: ident => $gd: ident, $modified: ident) => {
+        let mut $elt: std::rc::Rc<std::cell::RefCell<$ty>> =
+            std::rc::Rc::new(std::cell::RefCell::new(Default::default()));
+        {
+            let mut $elt = $elt.borrow_mut();
+            $elt.highlight = $rinfo.state.$elt != $rinfo.initial_state.$elt;
+            $elt.conflict = $rinfo.has_conflict(stringify!($elt));
+            $elt.error = $rinfo.field_error(stringify!($elt)).unwrap_or_default().to_string();
+            $elt.value = $crate::RspInputValue::to_input_value(&$rinfo.state.$elt);
+            $elt.id = $rinfo.element_id(stringify!($elt));
+            $modified = $modified || $elt.highlight;
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! rsp10_textarea {
+    ($elt: ident, $rinfo: ident => $gd: ident, $modified: ident) => {
+        $crate::rsp10_input!($crate::HtmlTextArea, $elt, $rinfo => $gd, $modified);
+        rsp10_gd!($gd, $elt);
+    };
+}
+
+/// Set min/max/step on the element after the macro, if needed
+#[macro_export]
+macro_rules! rsp10_number {
+    ($elt: ident, $rinfo: ident => $gd: ident, $modified: ident) => {
+        $crate::rsp10_input!($crate::HtmlNumber, $elt, $rinfo => $gd, $modified);
+        rsp10_gd!($gd, $elt);
+    };
+}
+
+/// For NaiveDate and NaiveDateTime fields, optional or not
+#[macro_export]
+macro_rules! rsp10_date {
+    ($elt: ident, $rinfo: ident => $gd: ident, $modified: ident) => {
+        $crate::rsp10_input!($crate::HtmlDate, $elt, $rinfo => $gd, $modified);
+        $elt.borrow_mut().input_type = $crate::input_type_of(&$rinfo.state.$elt).to_string();
+        rsp10_gd!($gd, $elt);
+    };
+}
+
+#[macro_export]
+macro_rules! rsp10_email {
+    ($elt: ident, $rinfo: ident => $gd: ident, $modified: ident) => {
+        $crate::rsp10_input!($crate::HtmlEmail, $elt, $rinfo => $gd, $modified);
+        rsp10_gd!($gd, $elt);
+    };
+}
+
+#[macro_export]
+macro_rules! rsp10_url {
+    ($elt: ident, $rinfo: ident => $gd: ident, $modified: ident) => {
+        $crate::rsp10_input!($crate::HtmlUrl, $elt, $rinfo => $gd, $modified);
+        rsp10_gd!($gd, $elt);
+    };
+}
+
+#[macro_export]
+macro_rules! rsp10_hidden {
+    ($elt: ident, $rinfo: ident => $gd: ident) => {
+        let mut $elt: std::rc::Rc<std::cell::RefCell<$crate::HtmlHidden>> =
+            std::rc::Rc::new(std::cell::RefCell::new(Default::default()));
+        {
+            let mut $elt = $elt.borrow_mut();
+            $elt.value = $crate::RspInputValue::to_input_value(&$rinfo.state.$elt);
+            $elt.id = $rinfo.element_id(stringify!($elt));
+        }
+        rsp10_gd!($gd, $elt);
+    };
+}
+
 #[macro_export]
 macro_rules! rsp10_check {
     ( $elt: ident, $rinfo: ident => $gd: ident, $modified: ident) => {
@@ -243,8 +435,10 @@ macro_rules! rsp10_check {
         {
             let mut $elt = $elt.borrow_mut();
             $elt.highlight = $rinfo.state.$elt != $rinfo.initial_state.$elt;
+            $elt.conflict = $rinfo.has_conflict(stringify!($elt));
+            $elt.error = $rinfo.field_error(stringify!($elt)).unwrap_or_default().to_string();
             $modified = $modified || $elt.highlight;
-            $elt.id = format!("{}", stringify!($elt));
+            $elt.id = $rinfo.element_id(stringify!($elt));
             $elt.checked = $rinfo.state.$elt;
         }
         rsp10_gd!($gd, $elt);
@@ -298,7 +492,7 @@ mod iron_support {
 
         pub fn stop_requested(&self) -> bool {
             if let Ok(lock) = self.stop_requested.read() {
-                lock.clone()
+                lock.clone() || crate::server_stop_requested()
             } else {
                 false
             }
@@ -349,13 +543,7 @@ mod iron_support {
 
     impl RspServer {
         pub fn read_default_secret() -> Result<Vec<u8>, std::io::Error> {
-            use std::fs::File;
-            use std::io::prelude::*;
-
-            let mut f = File::open(".secret")?;
-            let mut buffer = Vec::new();
-            f.read_to_end(&mut buffer)?;
-            Ok(buffer)
+            crate::state_codec::read_default_secret()
         }
 
         pub fn new() -> RspServer {
@@ -386,10 +574,12 @@ mod iron_support {
 
             let mut mount = Mount::new();
             mount.mount("/", main_handler);
-            mount.mount("/static/", Static::new(Path::new("staticfiles/")));
+            mount.mount("/static/", Static::new(Path::new(&crate::static_root())));
 
             let globals = Rsp10GlobalData::new();
             let my_secret = self.default_secret.clone().unwrap_or(rand_bytes());
+            crate::state_codec::set_state_secret(&my_secret);
+            crate::template_cache::preload_templates_at_boot();
             let mut ch = Chain::new(mount);
 
             // Enable session storage with signed cookies
@@ -0,0 +1,147 @@
+/// Post/Redirect/Get support
+///
+/// With `RspState::post_redirect_get()` enabled, a POST that would render
+/// the page stashes the result in a short-lived sealed cookie instead, and
+/// redirects to the same URL. The GET that follows renders the stash, so
+/// reloading the page in the browser does not submit the form again.
+
+use crate::conflict::RspFieldConflict;
+use crate::http_adapter::{RspCookie, RspRequestContext};
+use crate::state_codec;
+
+const PRG_COOKIE: &str = "rsp10_prg";
+/// Seconds the browser has to follow the redirect
+const PRG_MAX_AGE: i64 = 60;
+/// Browsers do not keep cookies much larger than 4K
+const PRG_MAX_COOKIE_LEN: usize = 3800;
+
+/// What the GET after the redirect needs to render the page
+#[derive(Serialize, Deserialize)]
+pub struct PrgStash<S, T> {
+    pub key: T,
+    pub state: S,
+    pub initial_state: S,
+    pub conflicts: Vec<RspFieldConflict>,
+}
+
+/// The stash as it is sealed, with the time it was made - the cookie expires
+/// with the Max-Age, but a client could keep sending it
+#[derive(Serialize, Deserialize)]
+struct SealedStash<X> {
+    issued: u64,
+    stash: X,
+}
+
+/// The stash is only valid for the path and the page type it was made for,
+/// and for the browser it was sent to
+fn sealing_field<S>(ctx: &RspRequestContext) -> String {
+    state_codec::state_binding::<S>(&format!("{} {}", PRG_COOKIE, ctx.path), ctx)
+}
+
+/// Seal the stash into a cookie for the current page, if it is small enough
+pub fn stash_cookie<S, T>(ctx: &RspRequestContext, stash: &PrgStash<S, T>) -> Option<RspCookie>
+where
+    S: serde::Serialize,
+    T: serde::Serialize,
+{
+    let json = serde_json::to_string(&SealedStash {
+        issued: state_codec::unix_time(),
+        stash,
+    })
+    .ok()?;
+    let sealed = state_codec::seal_state(&sealing_field::<S>(ctx), &json);
+    if sealed.len() > PRG_MAX_COOKIE_LEN {
+        warn!("State of {} too large for Post/Redirect/Get, rendering directly", ctx.path);
+        return None;
+    }
+    Some(
+        RspCookie::new(PRG_COOKIE, &sealed)
+            .path(&ctx.path)
+            .max_age(PRG_MAX_AGE)
+            .http_only(true)
+            .same_site("Lax"),
+    )
+}
+
+/// Whether the request carries a stash cookie, valid or not
+pub fn has_stash(ctx: &RspRequestContext) -> bool {
+    ctx.cookie(PRG_COOKIE).is_some()
+}
+
+/// Open the stash sent with the request - a stash made for another page or
+/// another browser is dropped
+pub fn take_stash<S, T>(ctx: &RspRequestContext) -> Option<PrgStash<S, T>>
+where
+    S: serde::de::DeserializeOwned,
+    T: serde::de::DeserializeOwned,
+{
+    let sealed = ctx.cookie(PRG_COOKIE)?;
+    let json = state_codec::open_state(&sealing_field::<S>(ctx), sealed)?;
+    let opened: SealedStash<PrgStash<S, T>> = serde_json::from_str(&json).ok()?;
+    if state_codec::unix_time().saturating_sub(opened.issued) > PRG_MAX_AGE as u64 {
+        warn!("Ignoring a stale Post/Redirect/Get stash for {}", ctx.path);
+        return None;
+    }
+    Some(opened.stash)
+}
+
+/// Cookie removing the stash once it is rendered
+pub fn removal_cookie(ctx: &RspRequestContext) -> RspCookie {
+    RspCookie::removal(PRG_COOKIE)
+        .path(&ctx.path)
+        .http_only(true)
+        .same_site("Lax")
+}
+
+/// The URL of the current page, to redirect to
+pub fn redirect_url(ctx: &RspRequestContext) -> String {
+    if ctx.query.is_empty() {
+        ctx.path.clone()
+    } else {
+        format!("{}?{}", ctx.path, ctx.query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(browser_key: &str, cookie: Option<&RspCookie>) -> RspRequestContext {
+        let mut ctx = RspRequestContext {
+            path: "/page".to_string(),
+            ..Default::default()
+        };
+        ctx.cookies.insert(state_codec::BROWSER_KEY_COOKIE.to_string(), browser_key.to_string());
+        if let Some(cookie) = cookie {
+            let cookie = cookie.to_string();
+            let value = cookie.split("; ").next().unwrap();
+            ctx.cookies.extend(crate::http_adapter::parse_cookie_header(value));
+        }
+        ctx
+    }
+
+    fn stash() -> PrgStash<String, u32> {
+        PrgStash {
+            key: 1,
+            state: "state".to_string(),
+            initial_state: "initial".to_string(),
+            conflicts: vec![],
+        }
+    }
+
+    #[test]
+    fn stash_opens_for_the_same_page_and_browser() {
+        let _secret = state_codec::with_test_secret(b"secret");
+        let cookie = stash_cookie(&request("browser", None), &stash()).unwrap();
+        let opened: PrgStash<String, u32> = take_stash(&request("browser", Some(&cookie))).unwrap();
+        assert_eq!((opened.key, opened.state.as_str()), (1, "state"));
+    }
+
+    #[test]
+    fn stash_of_another_browser_or_page_is_dropped() {
+        let _secret = state_codec::with_test_secret(b"secret");
+        let cookie = stash_cookie(&request("browser", None), &stash()).unwrap();
+        assert!(take_stash::<String, u32>(&request("other", Some(&cookie))).is_none());
+        assert!(take_stash::<Vec<String>, u32>(&request("browser", Some(&cookie))).is_none());
+    }
+}
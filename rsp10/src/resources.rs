@@ -0,0 +1,158 @@
+/// Where the templates and the static files come from
+///
+/// Both roots are relative to the working directory by default - `./templates`
+/// and `staticfiles/`. They can be set with `set_template_root` / `set_static_root`,
+/// or with the `RSP10_TEMPLATE_ROOT` / `RSP10_STATIC_ROOT` environment variables,
+/// so that the server does not depend on where it is started from.
+///
+/// For a self-contained binary the templates can be embedded at compile time:
+///
+/// ```ignore
+/// rsp10::set_embedded_templates(rsp10::embed_templates!("templates"));
+/// ```
+///
+/// The path is relative to the directory of the Cargo.toml of the crate, and all
+/// the `.mustache` files under it are included, with the partials in `html/` -
+/// `embed_templates!("templates", "jinja")` includes the `.jinja` ones instead.
+/// Once set, the embedded templates are used instead of the files.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+static TEMPLATE_ROOT: RwLock<Option<String>> = RwLock::new(None);
+static STATIC_ROOT: RwLock<Option<String>> = RwLock::new(None);
+static EMBEDDED_TEMPLATES: RwLock<Option<HashMap<String, &'static str>>> = RwLock::new(None);
+
+/// Partials included deeper than this are left out, they are most likely recursive
+const MAX_PARTIAL_DEPTH: usize = 16;
+
+fn configured_root(root: &RwLock<Option<String>>, env_var: &str, default: &str) -> String {
+    if let Ok(lock) = root.read() {
+        if let Some(ref r) = *lock {
+            return r.clone();
+        }
+    }
+    std::env::var(env_var).unwrap_or_else(|_| default.to_string())
+}
+
+fn set_root(root: &RwLock<Option<String>>, path: &str) {
+    if let Ok(mut lock) = root.write() {
+        *lock = Some(path.trim_end_matches('/').to_string());
+    }
+}
+
+/// Set the directory of the templates
+pub fn set_template_root(path: &str) {
+    set_root(&TEMPLATE_ROOT, path);
+    crate::template_cache::clear_template_cache();
+}
+
+/// The directory of the templates: as set by `set_template_root`,
+/// else `RSP10_TEMPLATE_ROOT`, else `./templates`
+pub fn template_root() -> String {
+    configured_root(&TEMPLATE_ROOT, "RSP10_TEMPLATE_ROOT", "./templates")
+}
+
+/// Set the directory served under `/static`
+pub fn set_static_root(path: &str) {
+    set_root(&STATIC_ROOT, path);
+}
+
+/// The directory served under `/static`: as set by `set_static_root`,
+/// else `RSP10_STATIC_ROOT`, else `staticfiles/`
+pub fn static_root() -> String {
+    configured_root(&STATIC_ROOT, "RSP10_STATIC_ROOT", "staticfiles/")
+}
+
+/// Use the templates embedded with `embed_templates!` instead of the files
+pub fn set_embedded_templates(templates: &[(&'static str, &'static str)]) {
+    if let Ok(mut lock) = EMBEDDED_TEMPLATES.write() {
+        *lock = Some(templates.iter().map(|(name, source)| (name.to_string(), *source)).collect());
+    }
+    crate::template_cache::clear_template_cache();
+}
+
+/// The source of the embedded template, if the templates are embedded
+pub fn embedded_template(name: &str) -> Option<&'static str> {
+    let lock = EMBEDDED_TEMPLATES.read().ok()?;
+    lock.as_ref()?.get(name).cloned()
+}
+
+pub fn has_embedded_templates() -> bool {
+    EMBEDDED_TEMPLATES.read().map(|lock| lock.is_some()).unwrap_or(false)
+}
+
+/// The error for a template which is not among the embedded ones
+pub(crate) fn not_embedded(name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("template {} not found, it was expected among the embedded templates", name),
+    )
+}
+
+/// The source of the template, embedded or from the file
+pub fn template_source(name: &str) -> std::io::Result<String> {
+    if has_embedded_templates() {
+        return embedded_template(name).map(|s| s.to_string()).ok_or_else(|| not_embedded(name));
+    }
+    std::fs::read_to_string(crate::template_path(name))
+}
+
+/// The names of all the templates of the template engine, e.g. `teststate` or `html/text`
+pub fn template_names() -> Vec<String> {
+    let mut names = vec![];
+    if let Ok(lock) = EMBEDDED_TEMPLATES.read() {
+        if let Some(ref embedded) = *lock {
+            names.extend(embedded.keys().cloned());
+            names.sort();
+            return names;
+        }
+    }
+    let extension = format!(".{}", crate::engine::template_engine().extension());
+    template_names_in(Path::new(&template_root()), "", &extension, &mut names);
+    names
+}
+
+fn template_names_in(dir: &Path, prefix: &str, extension: &str, names: &mut Vec<String>) {
+    let mut entries: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(rd) => rd.flatten().map(|e| e.path()).collect(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if path.is_dir() {
+            template_names_in(&path, &format!("{}{}/", prefix, file_name), extension, names);
+        } else if let Some(name) = file_name.strip_suffix(extension) {
+            names.push(format!("{}{}", prefix, name));
+        }
+    }
+}
+
+/// The embedded template with its partials pasted in - there are no files
+/// for the compiler to read them from. A missing partial is empty, as with the files.
+pub fn inline_partials(source: &str, depth: usize) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find("{{>") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 3..];
+        let end = match after.find("}}") {
+            Some(end) => end,
+            None => {
+                out.push_str(&rest[start..]);
+                return out;
+            }
+        };
+        let name = after[..end].trim();
+        if depth < MAX_PARTIAL_DEPTH {
+            if let Some(partial) = embedded_template(name) {
+                out.push_str(&inline_partials(partial, depth + 1));
+            }
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,287 @@
+/// Signing and sealing of the page state that travels through the browser
+///
+/// By default the `state_json` and `initial_state_json` hidden fields are
+/// sent out as `<signature>.<json>`, where the signature is an HMAC-SHA256
+/// over the binding and the JSON, keyed with the server secret. The binding
+/// names the hidden field, the page type and the browser, see `state_binding`,
+/// so that a state can not be replayed into another page or another session.
+///
+/// Pages that opt into `RspStateEncoding::Sealed` get the JSON compressed
+/// and encrypted with AES-256-GCM instead, sent as `~<base64 blob>`.
+///
+/// A state that comes back with a missing or wrong signature is rejected,
+/// and the page falls back to the freshly computed initial state.
+
+use std::io::prelude::*;
+use std::sync::RwLock;
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::http_adapter::{RspCookie, RspRequestContext};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SEALED_PREFIX: char = '~';
+const NONCE_LEN: usize = 12;
+
+/// Cookie with the random key of the browser, which the page state is bound to
+pub const BROWSER_KEY_COOKIE: &str = "rsp10_bk";
+
+/// How the page state is encoded in the hidden form fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RspStateEncoding {
+    /// Readable JSON with a signature - easy to debug
+    Signed,
+    /// Compressed and encrypted - neither readable nor modifiable in the browser
+    Sealed,
+}
+
+static STATE_SECRET: RwLock<Option<Vec<u8>>> = RwLock::new(None);
+
+/// Read the server secret from the `.secret` file in the current directory
+pub fn read_default_secret() -> Result<Vec<u8>, std::io::Error> {
+    use std::fs::File;
+
+    let mut f = File::open(".secret")?;
+    let mut buffer = Vec::new();
+    f.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Set the secret used to sign the page state
+pub fn set_state_secret(secret: &[u8]) {
+    if let Ok(mut lock) = STATE_SECRET.write() {
+        *lock = Some(secret.to_vec());
+    }
+}
+
+/// Get the secret used to sign the page state.
+///
+/// If none was set, the `.secret` file is used, and if that is missing too,
+/// a random secret is generated - which means the state of the pages
+/// will not survive a restart of the server.
+pub fn state_secret() -> Vec<u8> {
+    if let Ok(lock) = STATE_SECRET.read() {
+        if let Some(ref secret) = *lock {
+            return secret.clone();
+        }
+    }
+    let secret = read_default_secret().unwrap_or_else(|_| {
+        warn!("No .secret file found, using a random secret to sign the page state");
+        (0..64).map(|_| rand::random::<u8>()).collect()
+    });
+    set_state_secret(&secret);
+    secret
+}
+
+/// A new random browser key, with the cookie handing it to the browser
+pub fn new_browser_key() -> (String, RspCookie) {
+    let key: Vec<u8> = (0..24).map(|_| rand::random::<u8>()).collect();
+    let key = URL_SAFE_NO_PAD.encode(key);
+    let cookie = RspCookie::new(BROWSER_KEY_COOKIE, &key)
+        .path("/")
+        .http_only(true)
+        .same_site("Lax");
+    (key, cookie)
+}
+
+/// What the state in the hidden field of page `S` is signed or sealed for:
+/// the field, the page type and the browser key of the request
+pub fn state_binding<S>(field_name: &str, ctx: &RspRequestContext) -> String {
+    format!(
+        "{} {} {}",
+        field_name,
+        std::any::type_name::<S>(),
+        ctx.cookie(BROWSER_KEY_COOKIE).unwrap_or_default()
+    )
+}
+
+/// The field part of the binding, for the logs - the browser key stays out of them
+fn binding_name(binding: &str) -> &str {
+    binding.split(' ').next().unwrap_or_default()
+}
+
+fn state_mac(binding: &str, payload: &str) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(&state_secret()).expect("HMAC accepts any key length");
+    mac.update(binding.as_bytes());
+    mac.update(&[0]);
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Seconds since the epoch, for the expiry of what is sealed
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Sign the serialized state for the given binding
+pub fn sign_state(binding: &str, json: &str) -> String {
+    let signature = state_mac(binding, json).finalize().into_bytes();
+    format!("{}.{}", URL_SAFE_NO_PAD.encode(signature), json)
+}
+
+/// Verify the signed state for the given binding and return the JSON part
+pub fn verify_state<'a>(binding: &str, signed: &'a str) -> Option<&'a str> {
+    let (signature, json) = signed.split_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    match state_mac(binding, json).verify_slice(&signature) {
+        Ok(()) => Some(json),
+        Err(_) => {
+            warn!("Rejecting tampered {}", binding_name(binding));
+            None
+        }
+    }
+}
+
+fn sealing_cipher() -> Aes256Gcm {
+    let mut hasher = Sha256::new();
+    hasher.update(b"rsp10 state sealing key\0");
+    hasher.update(state_secret());
+    Aes256Gcm::new_from_slice(&hasher.finalize()).expect("SHA-256 output is a valid AES-256 key")
+}
+
+/// Compress and encrypt the serialized state for the given binding
+pub fn seal_state(binding: &str, json: &str) -> String {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(json.as_bytes()).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let nonce_bytes: Vec<u8> = (0..NONCE_LEN).map(|_| rand::random::<u8>()).collect();
+    let payload = Payload {
+        msg: &compressed,
+        aad: binding.as_bytes(),
+    };
+    let mut blob = nonce_bytes.clone();
+    blob.extend(
+        sealing_cipher()
+            .encrypt(Nonce::from_slice(&nonce_bytes), payload)
+            .expect("AES-GCM encryption failed"),
+    );
+    format!("{}{}", SEALED_PREFIX, URL_SAFE_NO_PAD.encode(blob))
+}
+
+/// Decrypt and decompress the sealed state for the given binding
+pub fn open_state(binding: &str, sealed: &str) -> Option<String> {
+    let blob = URL_SAFE_NO_PAD.decode(sealed.strip_prefix(SEALED_PREFIX)?).ok()?;
+    if blob.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce_bytes, ciphertext) = blob.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: binding.as_bytes(),
+    };
+    let compressed = match sealing_cipher().decrypt(Nonce::from_slice(nonce_bytes), payload) {
+        Ok(c) => c,
+        Err(_) => {
+            warn!("Rejecting tampered {}", binding_name(binding));
+            return None;
+        }
+    };
+    let mut json = String::new();
+    DeflateDecoder::new(&compressed[..]).read_to_string(&mut json).ok()?;
+    Some(json)
+}
+
+/// Encode the serialized state for the given binding
+pub fn encode_state(binding: &str, json: &str, encoding: RspStateEncoding) -> String {
+    match encoding {
+        RspStateEncoding::Signed => sign_state(binding, json),
+        RspStateEncoding::Sealed => seal_state(binding, json),
+    }
+}
+
+/// Decode the state for the given binding, whichever way it was encoded
+pub fn decode_state(binding: &str, encoded: &str) -> Option<String> {
+    if encoded.starts_with(SEALED_PREFIX) {
+        open_state(binding, encoded)
+    } else {
+        verify_state(binding, encoded).map(|json| json.to_string())
+    }
+}
+
+/// Set the secret for a test - it is global, so the tests
+/// depending on it must not run alongside each other
+#[cfg(test)]
+pub(crate) fn with_test_secret(secret: &[u8]) -> std::sync::MutexGuard<'static, ()> {
+    static SECRET: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let guard = SECRET.lock().unwrap_or_else(|e| e.into_inner());
+    set_state_secret(secret);
+    guard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_state_round_trips() {
+        let _secret = with_test_secret(b"secret");
+        let signed = sign_state("state_json Page key", r#"{"a":1}"#);
+        assert_eq!(verify_state("state_json Page key", &signed), Some(r#"{"a":1}"#));
+        assert_eq!(
+            decode_state("state_json Page key", &signed).as_deref(),
+            Some(r#"{"a":1}"#)
+        );
+    }
+
+    #[test]
+    fn tampered_state_is_rejected() {
+        let _secret = with_test_secret(b"secret");
+        let signed = sign_state("state_json Page key", r#"{"a":1}"#);
+        let (mac, _) = signed.split_once('.').unwrap();
+        assert_eq!(verify_state("state_json Page key", &format!("{}.{{\"a\":2}}", mac)), None);
+        assert_eq!(verify_state("state_json Page key", &format!("x{}", signed)), None);
+        assert_eq!(verify_state("state_json Page key", r#"{"a":1}"#), None);
+    }
+
+    #[test]
+    fn signed_state_is_bound() {
+        let _secret = with_test_secret(b"secret");
+        let signed = sign_state("state_json Page key", r#"{"a":1}"#);
+        assert_eq!(verify_state("initial_state_json Page key", &signed), None);
+        assert_eq!(verify_state("state_json OtherPage key", &signed), None);
+        assert_eq!(verify_state("state_json Page other", &signed), None);
+    }
+
+    #[test]
+    fn sealed_state_round_trips() {
+        let _secret = with_test_secret(b"secret");
+        let json = format!(r#"{{"text":"{}"}}"#, "x".repeat(1000));
+        let sealed = seal_state("state_json Page key", &json);
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(sealed.len() < json.len());
+        assert_eq!(open_state("state_json Page key", &sealed), Some(json.clone()));
+        assert_eq!(decode_state("state_json Page key", &sealed), Some(json));
+        assert_eq!(open_state("state_json OtherPage key", &sealed), None);
+    }
+
+    #[test]
+    fn state_under_another_secret_is_rejected() {
+        let _secret = with_test_secret(b"secret");
+        let signed = sign_state("state_json Page key", r#"{"a":1}"#);
+        let sealed = seal_state("state_json Page key", r#"{"a":1}"#);
+        set_state_secret(b"another secret");
+        assert_eq!(verify_state("state_json Page key", &signed), None);
+        assert_eq!(open_state("state_json Page key", &sealed), None);
+    }
+
+    #[test]
+    fn binding_names_the_page_and_the_browser() {
+        let mut ctx = RspRequestContext::default();
+        ctx.cookies.insert(BROWSER_KEY_COOKIE.to_string(), "key".to_string());
+        assert_eq!(state_binding::<u8>("state_json", &ctx), "state_json u8 key");
+        assert_ne!(state_binding::<u8>("state_json", &ctx), state_binding::<u16>("state_json", &ctx));
+    }
+}
@@ -0,0 +1,154 @@
+/// Compiled Mustache templates, kept for the lifetime of the server
+///
+/// The templates are compiled once, normally all of them at the startup by
+/// `preload_templates`, so that a broken template shows up at boot rather than
+/// on the first request to its page.
+///
+/// With the reload on (the default in debug builds, or with `RSP10_TEMPLATE_RELOAD=1`)
+/// the modification times of the template files are checked at most once a second,
+/// and a template is recompiled once any file under the template root is newer than
+/// it - the partials are compiled into the templates that include them.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use mustache::Template;
+
+use crate::engine::RspTemplateError;
+
+struct RspCachedTemplate {
+    template: Arc<Template>,
+    /// The newest modification time under the root when it was compiled
+    mtime: Option<SystemTime>,
+}
+
+static TEMPLATES: RwLock<Option<HashMap<String, RspCachedTemplate>>> = RwLock::new(None);
+static TEMPLATE_RELOAD: RwLock<Option<bool>> = RwLock::new(None);
+/// When the template root was last walked, and the newest modification time found
+static MTIME_CHECKED: Mutex<Option<(Instant, Option<SystemTime>)>> = Mutex::new(None);
+
+/// How long a walk of the template root stays good for, so that a busy
+/// server does not walk the directory on every request
+const MTIME_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Turn the recompiling of the changed templates on or off
+pub fn set_template_reload(reload: bool) {
+    if let Ok(mut lock) = TEMPLATE_RELOAD.write() {
+        *lock = Some(reload);
+    }
+}
+
+/// Whether the changed templates are recompiled: as set by `set_template_reload`,
+/// else by the `RSP10_TEMPLATE_RELOAD` environment variable, else in debug builds only
+pub fn template_reload() -> bool {
+    if let Ok(lock) = TEMPLATE_RELOAD.read() {
+        if let Some(reload) = *lock {
+            return reload;
+        }
+    }
+    let reload = match std::env::var("RSP10_TEMPLATE_RELOAD") {
+        Ok(v) => v != "0" && v != "false",
+        Err(_) => cfg!(debug_assertions),
+    };
+    set_template_reload(reload);
+    reload
+}
+
+/// The newest modification time of the files under the directory
+fn newest_mtime(dir: &Path) -> Option<SystemTime> {
+    let mut newest = None;
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        let mtime = if path.is_dir() {
+            newest_mtime(&path)
+        } else {
+            entry.metadata().and_then(|m| m.modified()).ok()
+        };
+        if mtime > newest {
+            newest = mtime;
+        }
+    }
+    newest
+}
+
+/// The newest modification time of the template files - the embedded ones never change
+pub(crate) fn templates_mtime() -> Option<SystemTime> {
+    if crate::resources::has_embedded_templates() {
+        return None;
+    }
+    let mut checked = MTIME_CHECKED.lock().unwrap_or_else(|e| e.into_inner());
+    match *checked {
+        Some((at, mtime)) if at.elapsed() < MTIME_CHECK_INTERVAL => mtime,
+        _ => {
+            let mtime = newest_mtime(Path::new(&crate::template_root()));
+            *checked = Some((Instant::now(), mtime));
+            mtime
+        }
+    }
+}
+
+fn compile(name: &str, mtime: Option<SystemTime>) -> Result<Arc<Template>, mustache::Error> {
+    let template = Arc::new(crate::maybe_compile_template(name)?);
+    if let Ok(mut lock) = TEMPLATES.write() {
+        lock.get_or_insert_with(HashMap::new).insert(
+            name.to_string(),
+            RspCachedTemplate {
+                template: template.clone(),
+                mtime,
+            },
+        );
+    }
+    Ok(template)
+}
+
+/// The compiled template, from the cache if it is there and up to date
+pub fn cached_template(name: &str) -> Result<Arc<Template>, mustache::Error> {
+    let reload = template_reload();
+    let mtime = if reload { templates_mtime() } else { None };
+    if let Ok(lock) = TEMPLATES.read() {
+        if let Some(cached) = lock.as_ref().and_then(|t| t.get(name)) {
+            if !reload || cached.mtime >= mtime {
+                return Ok(cached.template.clone());
+            }
+            debug!("Template {} changed, recompiling", name);
+        }
+    }
+    compile(name, mtime)
+}
+
+/// Compile all the templates of the template engine under the template root,
+/// returning how many there are, or the ones that fail to compile
+pub fn preload_templates() -> Result<usize, Vec<RspTemplateError>> {
+    let engine = crate::engine::template_engine();
+    let names = crate::resources::template_names();
+
+    let errors: Vec<RspTemplateError> = names.iter().filter_map(|name| engine.load(name).err()).collect();
+    if errors.is_empty() {
+        Ok(names.len())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Preload the templates at the startup of the server, reporting the broken ones
+pub(crate) fn preload_templates_at_boot() {
+    match preload_templates() {
+        Ok(0) => warn!("No templates found in {}", crate::template_root()),
+        Ok(count) if crate::resources::has_embedded_templates() => info!("Compiled {} embedded templates", count),
+        Ok(count) => info!("Compiled {} templates from {}", count, crate::template_root()),
+        Err(errors) => {
+            for e in errors {
+                error!("Template {} does not compile: {}", e.template, e.message);
+            }
+        }
+    }
+}
+
+/// Forget the compiled templates, they will be compiled again on their next use
+pub fn clear_template_cache() {
+    if let Ok(mut lock) = TEMPLATES.write() {
+        *lock = None;
+    }
+}
@@ -0,0 +1,303 @@
+/// Checking the templates of the pages against their states
+///
+/// A typo in a section name like `{{#ddMyDropdown}}` renders as nothing at all,
+/// so the checker goes through the template of each page and reports the names
+/// that neither the state nor the framework put into the data:
+///
+/// ```ignore
+/// for problem in simple_pages::check_templates() {
+///     eprintln!("{}", problem);
+/// }
+/// ```
+///
+/// The checker knows the Mustache templates only.
+/// Only the names outside of any section are checked, plus the `state.` and
+/// `initial_state.` ones everywhere - within the section of an element the names
+/// are the properties of the element.
+use crate::core::{RspKey, RspState, RspUserAuth};
+use std::collections::HashSet;
+use std::fmt;
+
+/// The names the framework adds to the data of every page, see `render`
+pub const RSP_TEMPLATE_NAMES: &[&str] = &[
+    "auth",
+    "state",
+    "state_key",
+    "initial_state",
+    "curr_initial_state",
+    "conflicts",
+    "has_conflicts",
+    "errors",
+    "has_errors",
+    "state_json",
+    "state_key_json",
+    "initial_state_json",
+    "curr_initial_state_json",
+];
+
+/// The hidden fields a form needs for the state to come back with the post,
+/// when the page does not have them added
+const RSP_REQUIRED_HIDDEN: &[&str] = &["state_json", "initial_state_json"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RspTemplateProblem {
+    pub page: String,
+    pub template: String,
+    pub message: String,
+}
+
+impl fmt::Display for RspTemplateProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.template, self.page, self.message)
+    }
+}
+
+/// A tag of the template
+#[derive(Debug, Clone, PartialEq)]
+enum Tag {
+    Variable(String),
+    Section(String),
+    Inverted(String),
+    Close(String),
+    Partial(String),
+}
+
+/// The tags of the template, skipping the comments.
+/// Custom delimiters are not supported, the tags after `{{=...=}}` are not returned.
+fn parse_tags(text: &str) -> Result<Vec<Tag>, String> {
+    let mut tags = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let close = if rest.starts_with('{') {
+            rest = &rest[1..];
+            "}}}"
+        } else {
+            "}}"
+        };
+        let end = rest.find(close).ok_or_else(|| "unclosed tag".to_string())?;
+        let tag = rest[..end].trim();
+        rest = &rest[end + close.len()..];
+
+        if close == "}}}" {
+            tags.push(Tag::Variable(tag.to_string()));
+            continue;
+        }
+        let mut chars = tag.chars();
+        let sigil = chars.next().unwrap_or(' ');
+        let name = chars.as_str().trim().to_string();
+        match sigil {
+            '!' => {}
+            '=' => break,
+            '#' => tags.push(Tag::Section(name)),
+            '^' => tags.push(Tag::Inverted(name)),
+            '/' => tags.push(Tag::Close(name)),
+            '>' => tags.push(Tag::Partial(name)),
+            '&' => tags.push(Tag::Variable(name)),
+            _ => tags.push(Tag::Variable(tag.to_string())),
+        }
+    }
+    Ok(tags)
+}
+
+/// What the template may refer to, and what it was found referring to
+struct RspTemplateScope<'a> {
+    page: &'a str,
+    names: HashSet<String>,
+    fields: HashSet<String>,
+    used: HashSet<String>,
+    seen_partials: HashSet<String>,
+    has_form: bool,
+    problems: Vec<RspTemplateProblem>,
+}
+
+impl<'a> RspTemplateScope<'a> {
+    fn problem(&mut self, template: &str, message: String) {
+        let problem = RspTemplateProblem {
+            page: self.page.to_string(),
+            template: template.to_string(),
+            message,
+        };
+        if !self.problems.contains(&problem) {
+            self.problems.push(problem);
+        }
+    }
+
+    fn check_name(&mut self, template: &str, name: &str, depth: usize) {
+        if name == "." {
+            return;
+        }
+        self.used.insert(name.to_string());
+        let mut parts = name.splitn(2, '.');
+        let first = parts.next().unwrap_or("");
+        if let Some(field) = parts.next() {
+            let field = field.split('.').next().unwrap_or("");
+            if (first == "state" || first == "initial_state") && !self.fields.contains(field) {
+                self.problem(template, format!("unknown state field '{}' in '{}'", field, name));
+                return;
+            }
+        }
+        if depth == 0 && !self.names.contains(first) {
+            self.problem(template, format!("unknown name '{}'", name));
+        }
+    }
+
+    /// Check the template, `depth` being the section level it is included at
+    fn check_template(&mut self, template: &str, depth: usize) {
+        match crate::resources::template_source(template) {
+            Ok(text) => self.check_text(template, &text, depth),
+            Err(e) => self.problem(template, format!("can not read the template: {}", e)),
+        }
+    }
+
+    /// Check the text of the template, reading the partials it includes
+    fn check_text(&mut self, template: &str, text: &str, depth: usize) {
+        if text.contains("<form") || text.contains("<FORM") {
+            self.has_form = true;
+        }
+        let tags = match parse_tags(text) {
+            Ok(tags) => tags,
+            Err(e) => {
+                self.problem(template, e);
+                return;
+            }
+        };
+
+        let mut sections: Vec<String> = vec![];
+        for tag in tags {
+            let depth = depth + sections.len();
+            match tag {
+                Tag::Variable(name) => self.check_name(template, &name, depth),
+                Tag::Section(name) | Tag::Inverted(name) => {
+                    self.check_name(template, &name, depth);
+                    sections.push(name);
+                }
+                Tag::Close(name) => match sections.pop() {
+                    Some(open) if open == name => {}
+                    Some(open) => self.problem(template, format!("'{}' closed by '{}'", open, name)),
+                    None => self.problem(template, format!("'{}' closed without being opened", name)),
+                },
+                Tag::Partial(name) => {
+                    // The element partials are included in the sections of the elements
+                    if depth == 0 && self.seen_partials.insert(name.clone()) {
+                        self.check_template(&name, depth);
+                    }
+                }
+            }
+        }
+        for open in sections {
+            self.problem(template, format!("'{}' is not closed", open));
+        }
+    }
+}
+
+/// Check the template (and the layout) of the page against the names of its data.
+/// A page which only ever redirects says so with `has_template`, and is not checked.
+pub fn check_page_template<S, T, TA>() -> Vec<RspTemplateProblem>
+where
+    S: RspState<T, TA>,
+    T: RspKey,
+    TA: RspUserAuth + serde::Serialize,
+{
+    if crate::engine::template_engine().extension() != "mustache" || !S::has_template() {
+        return vec![];
+    }
+    let page = std::any::type_name::<S>();
+    let template = if S::get_template_name() != "" {
+        S::get_template_name()
+    } else {
+        S::get_template_name_auto()
+    };
+    let layout = S::get_layout_name();
+
+    let mut names: HashSet<String> = RSP_TEMPLATE_NAMES.iter().map(|n| n.to_string()).collect();
+    names.extend(S::template_elements().iter().map(|n| n.to_string()));
+    names.extend(S::get_template_data_names());
+    if layout != "" {
+        names.insert(S::get_layout_slot());
+    }
+    let mut scope = RspTemplateScope {
+        page,
+        names,
+        fields: S::template_fields().iter().map(|n| n.to_string()).collect(),
+        used: HashSet::new(),
+        seen_partials: HashSet::new(),
+        has_form: false,
+        problems: vec![],
+    };
+
+    scope.check_template(&template, 0);
+    if layout != "" {
+        scope.check_template(&layout, 0);
+    }
+
+    if scope.has_form && !S::inject_state_fields() {
+        for hidden in RSP_REQUIRED_HIDDEN {
+            if !scope.used.contains(*hidden) {
+                scope.problem(&template, format!("the form has no '{}' hidden field", hidden));
+            }
+        }
+    }
+    scope.problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope() -> RspTemplateScope<'static> {
+        RspTemplateScope {
+            page: "test",
+            names: ["state", "ddColor"].iter().map(|n| n.to_string()).collect(),
+            fields: ["ddColor"].iter().map(|n| n.to_string()).collect(),
+            used: HashSet::new(),
+            seen_partials: HashSet::new(),
+            has_form: false,
+            problems: vec![],
+        }
+    }
+
+    fn messages(text: &str) -> Vec<String> {
+        let mut scope = scope();
+        scope.check_text("test", text, 0);
+        scope.problems.into_iter().map(|p| p.message).collect()
+    }
+
+    #[test]
+    fn tags_are_parsed() {
+        let tags = parse_tags("{{! note }}{{#ddColor}}{{> html/select }}{{/ddColor}}{{{ raw }}}{{^a}}{{& b}}").unwrap();
+        assert_eq!(
+            tags,
+            vec![
+                Tag::Section("ddColor".into()),
+                Tag::Partial("html/select".into()),
+                Tag::Close("ddColor".into()),
+                Tag::Variable("raw".into()),
+                Tag::Inverted("a".into()),
+                Tag::Variable("b".into()),
+            ]
+        );
+        assert_eq!(parse_tags("{{=<% %>=}}<% a %>").unwrap(), vec![]);
+        assert!(parse_tags("{{#ddColor}").is_err());
+    }
+
+    #[test]
+    fn known_names_pass() {
+        assert!(messages("{{#ddColor}}{{labeltext}}{{/ddColor}} {{state.ddColor}}").is_empty());
+    }
+
+    #[test]
+    fn bad_template_is_reported() {
+        let problems = messages("{{#ddColour}}{{/ddColour}} {{state.ddColour}} {{#ddColor}}{{/state}} {{^x}}");
+        assert_eq!(
+            problems,
+            vec![
+                "unknown name 'ddColour'",
+                "unknown state field 'ddColour' in 'state.ddColour'",
+                "'ddColor' closed by 'state'",
+                "unknown name 'x'",
+                "'x' is not closed",
+            ]
+        );
+    }
+}
@@ -0,0 +1,333 @@
+/// Field-level validation of the page state
+///
+/// The rules are declared on the state struct and checked by the code
+/// generated by `#[derive(RspState)]`:
+///
+/// ```ignore
+/// #[derive(Debug, Clone, Serialize, Deserialize, Default, RspStateDerive)]
+/// pub struct PageState {
+///     #[rsp_validate(required, length(max = 40))]
+///     txtName: String,
+///     #[rsp_validate(min = 0, max = 150, message = "Enter a realistic age")]
+///     txtAge: i32,
+///     #[rsp_validate(regex = "^[^@]+@[^@]+$", custom = check_domain)]
+///     txtEmail: String,
+/// }
+/// ```
+///
+/// A custom check is a `fn(&FieldType) -> Result<(), String>`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use regex::Regex;
+
+/// A validation failure of a state field
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RspFieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// The view of a field value that the validation rules need
+pub trait RspValidateValue {
+    /// Whether the value counts as not filled in
+    fn is_blank(&self) -> bool;
+
+    /// Numeric value, for the min/max rules
+    fn as_number(&self) -> Option<f64> {
+        None
+    }
+
+    /// Text value, for the length and regex rules
+    fn as_text(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl RspValidateValue for String {
+    fn is_blank(&self) -> bool {
+        self.trim().is_empty()
+    }
+    fn as_number(&self) -> Option<f64> {
+        self.trim().parse().ok()
+    }
+    fn as_text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl RspValidateValue for bool {
+    fn is_blank(&self) -> bool {
+        !*self
+    }
+}
+
+macro_rules! impl_validate_number {
+    ($($t: ty),*) => {
+        $(
+            impl RspValidateValue for $t {
+                fn is_blank(&self) -> bool {
+                    false
+                }
+                fn as_number(&self) -> Option<f64> {
+                    Some(*self as f64)
+                }
+            }
+        )*
+    };
+}
+
+impl_validate_number!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+impl RspValidateValue for chrono::NaiveDate {
+    fn is_blank(&self) -> bool {
+        false
+    }
+}
+
+impl RspValidateValue for chrono::NaiveDateTime {
+    fn is_blank(&self) -> bool {
+        false
+    }
+}
+
+impl<V: RspValidateValue> RspValidateValue for Option<V> {
+    fn is_blank(&self) -> bool {
+        self.as_ref().map_or(true, |v| v.is_blank())
+    }
+    fn as_number(&self) -> Option<f64> {
+        self.as_ref().and_then(|v| v.as_number())
+    }
+    fn as_text(&self) -> Option<&str> {
+        self.as_ref().and_then(|v| v.as_text())
+    }
+}
+
+impl<V> RspValidateValue for Vec<V> {
+    fn is_blank(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+/// Collects the errors while the rules of a state are checked
+#[derive(Debug, Default)]
+pub struct RspValidator {
+    errors: Vec<RspFieldError>,
+}
+
+impl RspValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_errors(self) -> Vec<RspFieldError> {
+        self.errors
+    }
+
+    /// Record an error, unless the field already has one
+    pub fn error(&mut self, field: &str, message: &str, custom_message: Option<&str>) {
+        if self.errors.iter().any(|e| e.field == field) {
+            return;
+        }
+        self.errors.push(RspFieldError {
+            field: field.to_string(),
+            message: custom_message.unwrap_or(message).to_string(),
+        });
+    }
+
+    pub fn required<V: RspValidateValue>(&mut self, field: &str, value: &V, message: Option<&str>) {
+        if value.is_blank() {
+            self.error(field, "This field is required", message);
+        }
+    }
+
+    pub fn min<V: RspValidateValue>(&mut self, field: &str, value: &V, min: f64, message: Option<&str>) {
+        if value.is_blank() && value.as_number().is_none() {
+            return;
+        }
+        match value.as_number() {
+            Some(n) if n >= min => {}
+            Some(_) => self.error(field, &format!("Must be at least {}", min), message),
+            None => self.error(field, "Must be a number", message),
+        }
+    }
+
+    pub fn max<V: RspValidateValue>(&mut self, field: &str, value: &V, max: f64, message: Option<&str>) {
+        if value.is_blank() && value.as_number().is_none() {
+            return;
+        }
+        match value.as_number() {
+            Some(n) if n <= max => {}
+            Some(_) => self.error(field, &format!("Must be at most {}", max), message),
+            None => self.error(field, "Must be a number", message),
+        }
+    }
+
+    /// Empty values pass, combine with `required` to forbid them
+    pub fn length<V: RspValidateValue>(
+        &mut self,
+        field: &str,
+        value: &V,
+        min: Option<usize>,
+        max: Option<usize>,
+        message: Option<&str>,
+    ) {
+        let len = match value.as_text() {
+            Some(text) if !text.is_empty() => text.chars().count(),
+            _ => return,
+        };
+        if let Some(min) = min {
+            if len < min {
+                self.error(field, &format!("Must be at least {} characters long", min), message);
+            }
+        }
+        if let Some(max) = max {
+            if len > max {
+                self.error(field, &format!("Must be at most {} characters long", max), message);
+            }
+        }
+    }
+
+    /// Empty values pass, combine with `required` to forbid them
+    pub fn regex<V: RspValidateValue>(&mut self, field: &str, value: &V, pattern: &str, message: Option<&str>) {
+        let text = match value.as_text() {
+            Some(text) if !text.is_empty() => text,
+            _ => return,
+        };
+        if !regex_matches(pattern, text) {
+            self.error(field, "Invalid format", message);
+        }
+    }
+
+    pub fn custom<V, F>(&mut self, field: &str, value: &V, check: F, message: Option<&str>)
+    where
+        F: Fn(&V) -> Result<(), String>,
+    {
+        if let Err(e) = check(value) {
+            self.error(field, &e, message);
+        }
+    }
+}
+
+static REGEX_CACHE: Mutex<Option<HashMap<String, Regex>>> = Mutex::new(None);
+
+fn regex_matches(pattern: &str, text: &str) -> bool {
+    let mut lock = REGEX_CACHE.lock().unwrap();
+    let cache = lock.get_or_insert_with(HashMap::new);
+    if !cache.contains_key(pattern) {
+        match Regex::new(pattern) {
+            Ok(re) => {
+                cache.insert(pattern.to_string(), re);
+            }
+            Err(e) => {
+                error!("Invalid validation regex {}: {}", pattern, e);
+                return false;
+            }
+        }
+    }
+    cache[pattern].is_match(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMAIL: &str = "^[^@\\s]+@[^@\\s]+\\.[^@\\s]+$";
+
+    fn errors(check: impl FnOnce(&mut RspValidator)) -> Vec<String> {
+        let mut v = RspValidator::new();
+        check(&mut v);
+        v.into_errors().into_iter().map(|e| format!("{}: {}", e.field, e.message)).collect()
+    }
+
+    #[test]
+    fn required() {
+        assert!(errors(|v| v.required("txtName", &"Bob".to_string(), None)).is_empty());
+        assert!(errors(|v| v.required("cbAgree", &true, None)).is_empty());
+        assert_eq!(errors(|v| v.required("txtName", &"  ".to_string(), None)), ["txtName: This field is required"]);
+        assert_eq!(errors(|v| v.required("txtName", &None::<String>, None)), ["txtName: This field is required"]);
+        assert_eq!(errors(|v| v.required("cbAgree", &false, Some("Please agree"))), ["cbAgree: Please agree"]);
+    }
+
+    #[test]
+    fn length() {
+        let name = "Zoë".to_string();
+        assert!(errors(|v| v.length("txtName", &name, Some(3), Some(3), None)).is_empty());
+        assert_eq!(
+            errors(|v| v.length("txtName", &name, Some(4), None, None)),
+            ["txtName: Must be at least 4 characters long"]
+        );
+        assert_eq!(
+            errors(|v| v.length("txtName", &name, None, Some(2), None)),
+            ["txtName: Must be at most 2 characters long"]
+        );
+    }
+
+    #[test]
+    fn range() {
+        assert!(errors(|v| {
+            v.min("txtAge", &0, 0.0, None);
+            v.max("txtAge", &150, 150.0, None);
+        })
+        .is_empty());
+        assert_eq!(errors(|v| v.min("txtAge", &-1, 0.0, None)), ["txtAge: Must be at least 0"]);
+        assert_eq!(errors(|v| v.max("txtAge", &151, 150.0, None)), ["txtAge: Must be at most 150"]);
+        assert_eq!(errors(|v| v.min("txtAge", &"abc".to_string(), 0.0, None)), ["txtAge: Must be a number"]);
+        assert_eq!(errors(|v| v.max("txtAge", &" 42 ".to_string(), 40.0, None)), ["txtAge: Must be at most 40"]);
+    }
+
+    #[test]
+    fn regex_and_email() {
+        assert!(errors(|v| v.regex("txtZip", &"1234".to_string(), "^[0-9]{4}$", None)).is_empty());
+        assert_eq!(errors(|v| v.regex("txtZip", &"12a4".to_string(), "^[0-9]{4}$", None)), ["txtZip: Invalid format"]);
+        assert!(errors(|v| v.regex("txtEmail", &"bob@example.com".to_string(), EMAIL, None)).is_empty());
+        assert_eq!(
+            errors(|v| v.regex("txtEmail", &"bob@example".to_string(), EMAIL, Some("Enter an e-mail address"))),
+            ["txtEmail: Enter an e-mail address"]
+        );
+        // An invalid pattern fails the field instead of panicking
+        assert_eq!(errors(|v| v.regex("txtZip", &"1".to_string(), "(", None)), ["txtZip: Invalid format"]);
+    }
+
+    #[test]
+    fn date() {
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 2, 29);
+        let not_in_past = |d: &Option<chrono::NaiveDate>| match d {
+            Some(d) if *d < chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap() => Err("Too early".to_string()),
+            _ => Ok(()),
+        };
+        assert!(errors(|v| v.required("dtStart", &day, None)).is_empty());
+        assert!(errors(|v| v.custom("dtStart", &day, not_in_past, None)).is_empty());
+        assert_eq!(errors(|v| v.required("dtStart", &None::<chrono::NaiveDate>, None)), ["dtStart: This field is required"]);
+        let early = chrono::NaiveDate::from_ymd_opt(2023, 12, 31);
+        assert_eq!(errors(|v| v.custom("dtStart", &early, not_in_past, None)), ["dtStart: Too early"]);
+    }
+
+    #[test]
+    fn empty_optional_field_passes_all_but_required() {
+        let empty: Option<String> = None;
+        let blank = Some(String::new());
+        for value in [&empty, &blank] {
+            assert!(errors(|v| {
+                v.length("txtNote", value, Some(3), Some(10), None);
+                v.min("txtNote", value, 1.0, None);
+                v.max("txtNote", value, 9.0, None);
+                v.regex("txtNote", value, EMAIL, None);
+            })
+            .is_empty());
+        }
+        assert!(errors(|v| v.min("numCount", &None::<i32>, 1.0, None)).is_empty());
+    }
+
+    #[test]
+    fn first_error_of_a_field_wins() {
+        assert_eq!(
+            errors(|v| {
+                v.required("txtName", &String::new(), None);
+                v.length("txtName", &String::new(), Some(3), None, None);
+            }),
+            ["txtName: This field is required"]
+        );
+    }
+}